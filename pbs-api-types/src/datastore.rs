@@ -206,6 +206,10 @@ pub enum DatastoreFSyncLevel {
             type: ChunkOrder,
             optional: true,
         },
+        "gc-sweep-batch-size": {
+            schema: GC_SWEEP_BATCH_SIZE_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Default)]
@@ -217,8 +221,21 @@ pub struct DatastoreTuning {
     pub chunk_order: Option<ChunkOrder>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sync_level: Option<DatastoreFSyncLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_sweep_batch_size: Option<usize>,
 }
 
+/// Default number of chunks garbage collection sweeps between progress updates and abort checks.
+pub const GC_SWEEP_BATCH_SIZE_DEFAULT: usize = 1024;
+
+pub const GC_SWEEP_BATCH_SIZE_SCHEMA: Schema = IntegerSchema::new(
+    "Number of chunks garbage collection sweeps between progress updates and abort checks.",
+)
+.minimum(1)
+.maximum(1024 * 1024)
+.default(GC_SWEEP_BATCH_SIZE_DEFAULT as isize)
+.schema();
+
 pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore tuning options")
     .format(&ApiStringFormat::PropertyString(
         &DatastoreTuning::API_SCHEMA,
@@ -1270,6 +1287,41 @@ pub struct GarbageCollectionStatus {
     pub still_bad: usize,
 }
 
+impl GarbageCollectionStatus {
+    /// Ratio of the data referenced by index files to the data actually stored on disk.
+    ///
+    /// Returns `1.0` if there is no data on disk.
+    pub fn deduplication_factor(&self) -> f64 {
+        if self.disk_bytes > 0 {
+            (self.index_data_bytes as f64) / (self.disk_bytes as f64)
+        } else {
+            1.0
+        }
+    }
+
+    /// On-disk usage as percentage of the data referenced by index files.
+    ///
+    /// Returns `None` if no index data was referenced.
+    pub fn on_disk_percentage(&self) -> Option<f64> {
+        if self.index_data_bytes > 0 {
+            Some((self.disk_bytes as f64 * 100.) / self.index_data_bytes as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Average size of the chunks stored on disk.
+    ///
+    /// Returns `None` if there are no chunks on disk.
+    pub fn average_chunk_size(&self) -> Option<u64> {
+        if self.disk_chunks > 0 {
+            Some(self.disk_bytes / (self.disk_chunks as u64))
+        } else {
+            None
+        }
+    }
+}
+
 #[api(
     properties: {
         "gc-status": {
@@ -1478,3 +1530,31 @@ pub fn print_store_and_ns(store: &str, ns: &BackupNamespace) -> String {
         format!("datastore '{}', namespace '{}'", store, ns)
     }
 }
+
+#[test]
+fn test_gc_status_derived_values() {
+    let status = GarbageCollectionStatus::default();
+    assert_eq!(status.deduplication_factor(), 1.0);
+    assert_eq!(status.on_disk_percentage(), None);
+    assert_eq!(status.average_chunk_size(), None);
+
+    // chunks on disk but nothing referenced anymore
+    let status = GarbageCollectionStatus {
+        disk_bytes: 4096,
+        disk_chunks: 2,
+        ..Default::default()
+    };
+    assert_eq!(status.deduplication_factor(), 0.0);
+    assert_eq!(status.on_disk_percentage(), None);
+    assert_eq!(status.average_chunk_size(), Some(2048));
+
+    let status = GarbageCollectionStatus {
+        index_data_bytes: 16384,
+        disk_bytes: 4096,
+        disk_chunks: 4,
+        ..Default::default()
+    };
+    assert_eq!(status.deduplication_factor(), 4.0);
+    assert_eq!(status.on_disk_percentage(), Some(25.0));
+    assert_eq!(status.average_chunk_size(), Some(1024));
+}
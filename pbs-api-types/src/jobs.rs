@@ -170,6 +170,13 @@ pub const VERIFICATION_OUTDATED_AFTER_SCHEMA: Schema =
         .minimum(0)
         .schema();
 
+pub const VERIFICATION_THREADS_SCHEMA: Schema =
+    IntegerSchema::new("Number of snapshots of a backup group verified concurrently.")
+        .minimum(1)
+        .maximum(32)
+        .default(1)
+        .schema();
+
 #[api(
     properties: {
         id: {
@@ -202,6 +209,10 @@ pub const VERIFICATION_OUTDATED_AFTER_SCHEMA: Schema =
             optional: true,
             schema: crate::NS_MAX_DEPTH_SCHEMA,
         },
+        threads: {
+            optional: true,
+            schema: VERIFICATION_THREADS_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
@@ -232,6 +243,9 @@ pub struct VerificationJobConfig {
     /// how deep the verify should go from the `ns` level downwards. Passing 0 verifies only the
     /// snapshots on the same level as the passed `ns`, or the datastore root if none.
     pub max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// how many snapshots of a group are verified concurrently
+    pub threads: Option<usize>,
 }
 
 impl VerificationJobConfig {
@@ -77,24 +77,69 @@ pub struct MaintenanceMode {
 }
 
 impl MaintenanceMode {
+    pub fn new(ty: MaintenanceType, message: Option<String>) -> Self {
+        Self { ty, message }
+    }
+
+    /// Returns the decoded operator supplied reason for the maintenance, if any.
+    pub fn message(&self) -> Option<Cow<str>> {
+        let message = self
+            .message
+            .as_deref()
+            .filter(|message| !message.is_empty())?;
+        Some(
+            percent_encoding::percent_decode_str(message)
+                .decode_utf8()
+                .unwrap_or(Cow::Borrowed(message)),
+        )
+    }
+
     pub fn check(&self, operation: Option<Operation>) -> Result<(), Error> {
         if self.ty == MaintenanceType::Delete {
             bail!("datastore is being deleted");
         }
 
-        let message = percent_encoding::percent_decode_str(self.message.as_deref().unwrap_or(""))
-            .decode_utf8()
-            .unwrap_or(Cow::Borrowed(""));
+        let reason = match self.message() {
+            Some(message) => format!(": {message}"),
+            None => String::new(),
+        };
 
         if let Some(Operation::Lookup) = operation {
             return Ok(());
         } else if self.ty == MaintenanceType::Offline {
-            bail!("offline maintenance mode: {}", message);
+            bail!("offline maintenance mode{reason}");
         } else if self.ty == MaintenanceType::ReadOnly {
             if let Some(Operation::Write) = operation {
-                bail!("read-only maintenance mode: {}", message);
+                bail!("read-only maintenance mode{reason}");
             }
         }
         Ok(())
     }
 }
+
+#[test]
+fn test_maintenance_mode_message() {
+    let mode = MaintenanceMode::new(
+        MaintenanceType::Offline,
+        Some("Migrating%20to%20new%20disks%2C%20back%20at%2018%3A00".to_string()),
+    );
+    let err = mode.check(Some(Operation::Read)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "offline maintenance mode: Migrating to new disks, back at 18:00"
+    );
+
+    let mode = MaintenanceMode::new(MaintenanceType::ReadOnly, Some("disk swap".to_string()));
+    assert!(mode.check(Some(Operation::Read)).is_ok());
+    let err = mode.check(Some(Operation::Write)).unwrap_err();
+    assert_eq!(err.to_string(), "read-only maintenance mode: disk swap");
+
+    // no message, no dangling separator
+    let mode = MaintenanceMode::new(MaintenanceType::Offline, None);
+    let err = mode.check(Some(Operation::Read)).unwrap_err();
+    assert_eq!(err.to_string(), "offline maintenance mode");
+
+    let mode = MaintenanceMode::new(MaintenanceType::Offline, Some(String::new()));
+    assert!(mode.message().is_none());
+    assert!(mode.check(Some(Operation::Lookup)).is_ok());
+}
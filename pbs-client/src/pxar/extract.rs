@@ -69,10 +69,70 @@ where
         .context("encountered unexpected error during extraction")
 }
 
-struct ExtractorIterState {
+/// Tracks which entries match the match list while descending into and leaving directories.
+///
+/// Shared by [`ExtractorIter`] and [`plan_restore`], so both decide the same way which entries
+/// get extracted.
+struct MatchState {
     match_stack: Vec<bool>,
-    err_path_stack: Vec<OsString>,
     current_match: bool,
+}
+
+/// The match decision for a single entry, see [`MatchState::check`].
+struct EntryMatch {
+    /// The entry itself is extracted.
+    did_match: bool,
+    /// For directories: the directory is created even if nothing inside of it is extracted.
+    create_dir: bool,
+}
+
+impl MatchState {
+    fn new(options: &PxarExtractOptions) -> Self {
+        Self {
+            match_stack: Vec::new(),
+            current_match: options.extract_match_default,
+        }
+    }
+
+    fn check(&self, match_list: &[MatchEntry], entry: &Entry) -> EntryMatch {
+        // We can `unwrap()` safely here because we get a `Result<_, std::convert::Infallible>`
+        let match_result = match_list
+            .matches(
+                entry.path().as_os_str().as_bytes(),
+                entry.metadata().file_type() as u32,
+            )
+            .unwrap();
+
+        let did_match = match match_result {
+            Some(MatchType::Include) => true,
+            Some(MatchType::Exclude) => false,
+            None => self.current_match,
+        };
+
+        EntryMatch {
+            did_match,
+            create_dir: self.current_match && match_result != Some(MatchType::Exclude),
+        }
+    }
+
+    /// We're starting a new directory, push our old matching state and replace it with our new
+    /// one.
+    fn enter_directory(&mut self, dir_match: &EntryMatch) {
+        self.match_stack.push(self.current_match);
+        self.current_match = dir_match.did_match;
+    }
+
+    /// We left a directory, get back our previous matching state. This is in sync with the
+    /// directory stack, so this should never be empty except for the final goodbye table, in
+    /// which case we get back to the default of `true`.
+    fn leave_directory(&mut self) {
+        self.current_match = self.match_stack.pop().unwrap_or(true);
+    }
+}
+
+struct ExtractorIterState {
+    matches: MatchState,
+    err_path_stack: Vec<OsString>,
     end_reached: bool,
 }
 
@@ -94,9 +154,8 @@ where
 impl ExtractorIterState {
     fn new(options: &PxarExtractOptions) -> Self {
         Self {
-            match_stack: Vec::new(),
+            matches: MatchState::new(options),
             err_path_stack: Vec::new(),
-            current_match: options.extract_match_default,
             end_reached: false,
         }
     }
@@ -251,36 +310,23 @@ where
 
         self.extractor.set_path(entry.path().as_os_str().to_owned());
 
-        // We can `unwrap()` safely here because we get a `Result<_, std::convert::Infallible>`
-        let match_result = self
-            .match_list
-            .matches(
-                entry.path().as_os_str().as_bytes(),
-                metadata.file_type() as u32,
-            )
-            .unwrap();
-
-        let did_match = match match_result {
-            Some(MatchType::Include) => true,
-            Some(MatchType::Exclude) => false,
-            None => self.state.current_match,
-        };
+        let entry_match = self.state.matches.check(self.match_list, &entry);
 
-        let extract_res = match (did_match, entry.kind()) {
+        let extract_res = match (entry_match.did_match, entry.kind()) {
             (_, EntryKind::Directory) => {
                 self.callback(entry.path());
 
-                let create = self.state.current_match && match_result != Some(MatchType::Exclude);
                 let res = self
                     .extractor
-                    .enter_directory(file_name_os.to_owned(), metadata.clone(), create)
+                    .enter_directory(
+                        file_name_os.to_owned(),
+                        metadata.clone(),
+                        entry_match.create_dir,
+                    )
                     .context(PxarExtractContext::EnterDirectory);
 
                 if res.is_ok() {
-                    // We're starting a new directory, push our old matching state and replace it with
-                    // our new one:
-                    self.state.match_stack.push(self.state.current_match);
-                    self.state.current_match = did_match;
+                    self.state.matches.enter_directory(&entry_match);
 
                     // When we hit the goodbye table we'll try to apply metadata to the directory, but
                     // the Goodbye entry will not contain the path, so push it to our path stack for
@@ -303,10 +349,7 @@ where
                     .context(PxarExtractContext::LeaveDirectory);
 
                 if res.is_ok() {
-                    // We left a directory, also get back our previous matching state. This is in sync
-                    // with `dir_stack` so this should never be empty except for the final goodbye
-                    // table, in which case we get back to the default of `true`.
-                    self.state.current_match = self.state.match_stack.pop().unwrap_or(true);
+                    self.state.matches.leave_directory();
                 }
 
                 res
@@ -384,6 +427,139 @@ where
     }
 }
 
+/// Kind of entry a [`RestoreAction`] would create.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreEntryKind {
+    Directory,
+    File,
+    Symlink,
+    Hardlink,
+    Device,
+    Fifo,
+    Socket,
+}
+
+/// An entry [`extract_archive`] would create, as returned by [`plan_restore`].
+#[derive(Debug, Clone)]
+pub struct RestoreAction {
+    /// Target path of the entry.
+    pub path: PathBuf,
+    /// Kind of the entry.
+    pub kind: RestoreEntryKind,
+    /// Size in bytes for regular files, 0 otherwise.
+    pub size: u64,
+    /// Something already exists at `path`.
+    pub exists: bool,
+    /// The existing entry would be replaced according to the overwrite flags.
+    ///
+    /// If `exists` is set but this is not, extracting this entry would fail, except for
+    /// directories when existing directories are allowed.
+    pub overwrite: bool,
+}
+
+/// Walks the archive like [`extract_archive`] does, but without touching `destination`.
+///
+/// Returns the entries an extraction with the same `feature_flags` and `options` would create,
+/// using the same match list handling, and whether they collide with existing files.
+/// Directories which are only created implicitly, because they contain a matching entry, are
+/// not listed.
+pub fn plan_restore<T>(
+    mut decoder: pxar::decoder::Decoder<T>,
+    destination: &Path,
+    feature_flags: Flags,
+    options: &PxarExtractOptions,
+) -> Result<Vec<RestoreAction>, Error>
+where
+    T: pxar::decoder::SeqRead,
+{
+    decoder.enable_goodbye_entries(true);
+
+    let root = decoder
+        .next()
+        .context("found empty pxar archive")?
+        .context("error reading pxar archive")?;
+
+    if !root.is_dir() {
+        bail!("pxar archive does not start with a directory entry!");
+    }
+
+    let mut dir_level = 1;
+    let mut matches = MatchState::new(options);
+    let mut actions = Vec::new();
+
+    while let Some(entry) = decoder.next() {
+        let entry = entry.context("error reading pxar archive")?;
+
+        if let EntryKind::GoodbyeTable = entry.kind() {
+            dir_level -= 1;
+            matches.leave_directory();
+            continue;
+        }
+
+        if entry.file_name().as_bytes().contains(&b'/') {
+            bail!("archive file entry contains slashes, which is invalid and a security concern");
+        }
+
+        let entry_match = matches.check(options.match_list, &entry);
+
+        let overwrite_flags = options.overwrite_flags;
+        let (kind, size, overwrite) = match (entry_match.did_match, entry.kind()) {
+            (_, EntryKind::Directory) => {
+                dir_level += 1;
+                matches.enter_directory(&entry_match);
+
+                if !entry_match.create_dir {
+                    continue;
+                }
+                (RestoreEntryKind::Directory, 0, false)
+            }
+            (true, EntryKind::File { size, .. }) => (
+                RestoreEntryKind::File,
+                *size,
+                overwrite_flags.contains(OverwriteFlags::FILE),
+            ),
+            (true, EntryKind::Symlink(_)) => (
+                RestoreEntryKind::Symlink,
+                0,
+                overwrite_flags.contains(OverwriteFlags::SYMLINK),
+            ),
+            (true, EntryKind::Hardlink(_)) => (
+                RestoreEntryKind::Hardlink,
+                0,
+                overwrite_flags.contains(OverwriteFlags::HARDLINK),
+            ),
+            (true, EntryKind::Device(_)) if feature_flags.contains(Flags::WITH_DEVICE_NODES) => {
+                (RestoreEntryKind::Device, 0, false)
+            }
+            (true, EntryKind::Fifo) if feature_flags.contains(Flags::WITH_FIFOS) => {
+                (RestoreEntryKind::Fifo, 0, false)
+            }
+            (true, EntryKind::Socket) if feature_flags.contains(Flags::WITH_SOCKETS) => {
+                (RestoreEntryKind::Socket, 0, false)
+            }
+            _ => continue, // skipped by extraction
+        };
+
+        let relative = entry.path().strip_prefix("/").unwrap_or(entry.path());
+        let path = destination.join(relative);
+        let exists = std::fs::symlink_metadata(&path).is_ok();
+
+        actions.push(RestoreAction {
+            path,
+            kind,
+            size,
+            exists,
+            overwrite: exists && overwrite,
+        });
+    }
+
+    if dir_level != 0 {
+        bail!("unexpected eof while decoding pxar archive");
+    }
+
+    Ok(actions)
+}
+
 /// Provides additional [context][C] for [`anyhow::Error`]s that are returned
 /// while traversing an [`ExtractorIter`]. The [`PxarExtractContext`] can then
 /// be accessed [via `anyhow`'s facilities][A] and may aid during error handling.
@@ -1300,3 +1476,101 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn create_test_archive(source: &Path, archive: &Path) -> Result<(), Error> {
+        let dir = Dir::open(source, OFlag::O_DIRECTORY | OFlag::O_CLOEXEC, Mode::empty())?;
+        let writer = pxar::encoder::sync::StandardWriter::new(std::fs::File::create(archive)?);
+
+        crate::pxar::create_archive(
+            dir,
+            writer,
+            Flags::DEFAULT,
+            |_| Ok(()),
+            None,
+            crate::pxar::PxarCreateOptions {
+                entries_max: crate::pxar::ENCODER_MAX_ENTRIES,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    fn plan(archive: &Path, target: &Path, overwrite_flags: OverwriteFlags) -> Vec<RestoreAction> {
+        let decoder =
+            pxar::decoder::Decoder::from_std(std::fs::File::open(archive).unwrap()).unwrap();
+        let options = PxarExtractOptions {
+            match_list: &[],
+            extract_match_default: true,
+            allow_existing_dirs: true,
+            overwrite_flags,
+            on_error: None,
+        };
+        plan_restore(decoder, target, Flags::DEFAULT, &options).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_plan_restore() -> Result<(), Error> {
+        let testdir = PathBuf::from(".testdir-plan-restore");
+        if testdir.exists() {
+            std::fs::remove_dir_all(&testdir)?;
+        }
+
+        let source = testdir.join("source");
+        std::fs::create_dir_all(source.join("sub"))?;
+        std::fs::write(source.join("file.txt"), b"archived")?;
+        std::fs::write(source.join("sub/other.txt"), b"archived too")?;
+
+        let archive = testdir.join("archive.pxar");
+        create_test_archive(&source, &archive).await?;
+
+        let target = testdir.join("target");
+        std::fs::create_dir(&target)?;
+        std::fs::write(target.join("file.txt"), b"important")?;
+
+        let actions = plan(&archive, &target, OverwriteFlags::empty());
+
+        let file = actions
+            .iter()
+            .find(|a| a.path == target.join("file.txt"))
+            .unwrap();
+        assert_eq!(file.kind, RestoreEntryKind::File);
+        assert_eq!(file.size, 8);
+        assert!(file.exists);
+        assert!(!file.overwrite);
+
+        let sub = actions
+            .iter()
+            .find(|a| a.path == target.join("sub"))
+            .unwrap();
+        assert_eq!(sub.kind, RestoreEntryKind::Directory);
+        assert!(!sub.exists);
+
+        let other = actions
+            .iter()
+            .find(|a| a.path == target.join("sub/other.txt"))
+            .unwrap();
+        assert_eq!(other.size, 12);
+        assert!(!other.exists);
+
+        assert_eq!(actions.len(), 3);
+
+        // nothing was written
+        assert!(!target.join("sub").exists());
+        assert_eq!(std::fs::read(target.join("file.txt"))?, b"important");
+
+        let actions = plan(&archive, &target, OverwriteFlags::FILE);
+        let file = actions
+            .iter()
+            .find(|a| a.path == target.join("file.txt"))
+            .unwrap();
+        assert!(file.exists && file.overwrite);
+
+        std::fs::remove_dir_all(&testdir)?;
+
+        Ok(())
+    }
+}
@@ -1,11 +1,12 @@
-use std::io::Write;
-//use std::os::unix::io::FromRawFd;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, IntoRawFd, OwnedFd};
 use std::path::Path;
 use std::pin::Pin;
+use std::sync::mpsc::SyncSender;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
-use anyhow::{format_err, Error};
+use anyhow::{bail, format_err, Error};
 use futures::future::{AbortHandle, Abortable};
 use futures::stream::Stream;
 use nix::dir::Dir;
@@ -13,10 +14,72 @@ use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 
 use proxmox_async::blocking::TokioWriterAdapter;
-use proxmox_io::StdChannelWriter;
 
 use pbs_datastore::catalog::CatalogWriter;
 
+/// Default size of the chunks emitted by [`PxarBackupStream`].
+///
+/// Up to 10 of these are queued in the channel to the stream, so this also bounds the memory
+/// held by a running encoder.
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Writer collecting data into fixed size buffers, which are sent through a channel.
+///
+/// A full buffer is handed out as a whole and replaced by a newly allocated one, so the
+/// data is not copied a second time before it reaches the consumer.
+struct ChannelBufferWriter {
+    tx: SyncSender<Result<Vec<u8>, Error>>,
+    buffer: Vec<u8>,
+    buffer_size: usize,
+}
+
+impl ChannelBufferWriter {
+    fn new(tx: SyncSender<Result<Vec<u8>, Error>>, buffer_size: usize) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(buffer_size),
+            buffer_size,
+        }
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let buffer = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.buffer_size));
+
+        self.tx
+            .send(Ok(buffer))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "pxar stream receiver gone"))
+    }
+}
+
+impl Write for ChannelBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len().min(self.buffer_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..count]);
+
+        if self.buffer.len() == self.buffer_size {
+            self.send_buffer()?;
+        }
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+impl Drop for ChannelBufferWriter {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.tx.send(Ok(std::mem::take(&mut self.buffer)));
+        }
+    }
+}
+
 /// Stream implementation to encode and upload .pxar archives.
 ///
 /// The hyper client needs an async Stream for file upload, so we
@@ -26,32 +89,52 @@ pub struct PxarBackupStream {
     rx: Option<std::sync::mpsc::Receiver<Result<Vec<u8>, Error>>>,
     handle: Option<AbortHandle>,
     error: Arc<Mutex<Option<String>>>,
+    total_bytes: u64,
 }
 
 impl Drop for PxarBackupStream {
     fn drop(&mut self) {
+        // Close the receiving side first, so an encoder blocked on a full
+        // channel fails its next write and exits, instead of waiting forever
+        // for a consumer that is gone.
         self.rx = None;
-        self.handle.take().unwrap().abort();
+        match self.handle.take() {
+            Some(handle) => handle.abort(),
+            None => log::error!("pxar backup stream dropped without encoder handle"),
+        }
     }
 }
 
 impl PxarBackupStream {
+    /// Creates a stream encoding `dir`.
+    ///
+    /// The directory handle is moved into the encoder task and closed once
+    /// encoding finished or the stream got dropped.
     pub fn new<W: Write + Send + 'static>(
         dir: Dir,
         catalog: Arc<Mutex<CatalogWriter<W>>>,
         options: crate::pxar::PxarCreateOptions,
     ) -> Result<Self, Error> {
-        let (tx, rx) = std::sync::mpsc::sync_channel(10);
+        Self::new_with_buffer_size(dir, catalog, options, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Like [`new`](Self::new), but emits chunks of `buffer_size` bytes.
+    pub fn new_with_buffer_size<W: Write + Send + 'static>(
+        dir: Dir,
+        catalog: Arc<Mutex<CatalogWriter<W>>>,
+        options: crate::pxar::PxarCreateOptions,
+        buffer_size: usize,
+    ) -> Result<Self, Error> {
+        if buffer_size == 0 {
+            bail!("pxar stream buffer size must not be zero");
+        }
 
-        let buffer_size = 256 * 1024;
+        let (tx, rx) = std::sync::mpsc::sync_channel(10);
 
         let error = Arc::new(Mutex::new(None));
         let error2 = Arc::clone(&error);
         let handler = async move {
-            let writer = TokioWriterAdapter::new(std::io::BufWriter::with_capacity(
-                buffer_size,
-                StdChannelWriter::new(tx),
-            ));
+            let writer = TokioWriterAdapter::new(ChannelBufferWriter::new(tx, buffer_size));
 
             let writer = pxar::encoder::sync::StandardWriter::new(writer);
             if let Err(err) = crate::pxar::create_archive(
@@ -80,6 +163,7 @@ impl PxarBackupStream {
             rx: Some(rx),
             handle: Some(handle),
             error,
+            total_bytes: 0,
         })
     }
 
@@ -92,29 +176,202 @@ impl PxarBackupStream {
 
         Self::new(dir, catalog, options)
     }
+
+    /// Creates a stream encoding the directory referred to by an already opened `fd`.
+    ///
+    /// Ownership of `fd` is transferred to the stream, see [`new`](Self::new). Contrary
+    /// to [`open`](Self::open), no path lookup happens, so this can be used for
+    /// directories which were pinned earlier and are no longer reachable by path, e.g.
+    /// inside another mount namespace.
+    pub fn from_fd<W: Write + Send + 'static>(
+        fd: OwnedFd,
+        catalog: Arc<Mutex<CatalogWriter<W>>>,
+        options: crate::pxar::PxarCreateOptions,
+    ) -> Result<Self, Error> {
+        let stat = nix::sys::stat::fstat(fd.as_raw_fd())?;
+        if (stat.st_mode & libc::S_IFMT) != libc::S_IFDIR {
+            bail!("file descriptor does not refer to a directory");
+        }
+
+        let dir = Dir::from_fd(fd.into_raw_fd())?;
+
+        Self::new(dir, catalog, options)
+    }
+
+    /// Returns the number of archive bytes emitted by this stream so far.
+    ///
+    /// The stream is consumed lazily, so the value is only the final archive
+    /// size once the stream is exhausted. If the encoder failed, this is the
+    /// amount of data emitted before the error was returned.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
 }
 
 impl Stream for PxarBackupStream {
     type Item = Result<Vec<u8>, Error>;
 
-    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<Self::Item>> {
         {
             // limit lock scope
             let error = self.error.lock().unwrap();
             if let Some(ref msg) = *error {
-                return Poll::Ready(Some(Err(format_err!("{}", msg))));
+                return Poll::Ready(Some(Err(format_err!(
+                    "{msg} (after {} bytes)",
+                    self.total_bytes
+                ))));
             }
         }
 
         match proxmox_async::runtime::block_in_place(|| self.rx.as_ref().unwrap().recv()) {
-            Ok(data) => Poll::Ready(Some(data)),
+            Ok(Ok(data)) => {
+                self.total_bytes += data.len() as u64;
+                Poll::Ready(Some(Ok(data)))
+            }
+            Ok(Err(err)) => Poll::Ready(Some(Err(err))),
             Err(_) => {
                 let error = self.error.lock().unwrap();
                 if let Some(ref msg) = *error {
-                    return Poll::Ready(Some(Err(format_err!("{}", msg))));
+                    return Poll::Ready(Some(Err(format_err!(
+                        "{msg} (after {} bytes)",
+                        self.total_bytes
+                    ))));
                 }
                 Poll::Ready(None) // channel closed, no error
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn create_test_dir(name: &str, files: usize, file_size: usize) -> Result<PathBuf, Error> {
+        let path = PathBuf::from(format!(".testdir-pxar-{name}"));
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        std::fs::create_dir(&path)?;
+
+        let data: Vec<u8> = (0..file_size).map(|i| (i % 251) as u8).collect();
+        for i in 0..files {
+            std::fs::write(path.join(format!("file-{i}")), &data)?;
+        }
+
+        Ok(path)
+    }
+
+    fn test_options() -> crate::pxar::PxarCreateOptions {
+        crate::pxar::PxarCreateOptions {
+            entries_max: crate::pxar::ENCODER_MAX_ENTRIES,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_channel_writer_does_not_copy() -> Result<(), Error> {
+        let buffer_size = 64 * 1024;
+        let (tx, rx) = std::sync::mpsc::sync_channel(16);
+
+        let mut writer = ChannelBufferWriter::new(tx, buffer_size);
+        let data = vec![0xaa; 4096];
+        for _ in 0..(10 * buffer_size / data.len()) {
+            writer.write_all(&data)?;
+        }
+        writer.write_all(&data[..100])?;
+        drop(writer);
+
+        // one chunk per buffer, not one per write
+        let chunks: Vec<Vec<u8>> = rx.iter().collect::<Result<_, _>>()?;
+        assert_eq!(chunks.len(), 11);
+        for chunk in &chunks[..10] {
+            assert_eq!(chunk.len(), buffer_size);
+        }
+        assert_eq!(chunks[10].len(), 100);
+
+        // every emitted chunk is the buffer itself, not a copy sized to fit
+        for chunk in &chunks {
+            assert_eq!(chunk.capacity(), buffer_size);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_total_bytes() -> Result<(), Error> {
+        let path = create_test_dir("total-bytes", 16, 64 * 1024)?;
+
+        let catalog = Arc::new(Mutex::new(CatalogWriter::new(std::io::sink())?));
+        let mut stream = PxarBackupStream::open(&path, catalog, test_options())?;
+
+        let mut sum = 0;
+        while let Some(data) = stream.next().await {
+            sum += data?.len() as u64;
+        }
+
+        assert!(sum > 16 * 64 * 1024);
+        assert_eq!(stream.total_bytes(), sum);
+
+        std::fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_from_fd() -> Result<(), Error> {
+        let path = create_test_dir("from-fd", 4, 64 * 1024)?;
+        let fd: OwnedFd = std::fs::File::open(&path)?.into();
+
+        // the path must not be needed anymore once the fd is open
+        let moved = PathBuf::from(".testdir-pxar-from-fd-moved");
+        if moved.exists() {
+            std::fs::remove_dir_all(&moved)?;
+        }
+        std::fs::rename(&path, &moved)?;
+
+        let catalog = Arc::new(Mutex::new(CatalogWriter::new(std::io::sink())?));
+        let mut stream = PxarBackupStream::from_fd(fd, catalog, test_options())?;
+
+        while let Some(data) = stream.next().await {
+            data?;
+        }
+        assert!(stream.total_bytes() > 4 * 64 * 1024);
+
+        let file: OwnedFd = std::fs::File::open(moved.join("file-0"))?.into();
+        let catalog = Arc::new(Mutex::new(CatalogWriter::new(std::io::sink())?));
+        assert!(PxarBackupStream::from_fd(file, catalog, test_options()).is_err());
+
+        std::fs::remove_dir_all(&moved)?;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_drop_stream_early() -> Result<(), Error> {
+        // a lot more data than fits into the channel, so the encoder blocks
+        let path = create_test_dir("drop-early", 64, 256 * 1024)?;
+
+        let catalog = Arc::new(Mutex::new(CatalogWriter::new(std::io::sink())?));
+        let mut stream = PxarBackupStream::open(&path, catalog, test_options())?;
+
+        let first = stream.next().await;
+        assert!(matches!(first, Some(Ok(_))));
+
+        // give the encoder time to fill up the channel
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let start = Instant::now();
+        drop(stream);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        std::fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use anyhow::{bail, Error};
 use nix::sys::stat::Mode;
+use nix::unistd::{Gid, Uid};
 use once_cell::sync::OnceCell;
 
 use proxmox_sys::fs::{create_path, CreateOptions};
@@ -28,6 +29,8 @@ struct ConfigVersionCacheDataInner {
     // datastore (datastore.cfg) generation/version
     // FIXME: remove with PBS 3.0
     datastore_generation: AtomicUsize,
+    // Node config (node.cfg) generation/version.
+    node_config_generation: AtomicUsize,
     // Add further atomics here
 }
 
@@ -93,20 +96,24 @@ impl ConfigVersionCache {
     fn open() -> Result<Arc<Self>, Error> {
         let user = crate::backup_user()?;
 
+        Self::open_path(Path::new(FILE_PATH), user.uid, user.gid)
+    }
+
+    /// Open a version cache at a custom location, bypassing the singleton (used for tests).
+    pub fn open_path(file_path: &Path, uid: Uid, gid: Gid) -> Result<Arc<Self>, Error> {
         let dir_opts = CreateOptions::new()
             .perm(Mode::from_bits_truncate(0o770))
-            .owner(user.uid)
-            .group(user.gid);
+            .owner(uid)
+            .group(gid);
 
-        let file_path = Path::new(FILE_PATH);
         let dir_path = file_path.parent().unwrap();
 
         create_path(dir_path, Some(dir_opts.clone()), Some(dir_opts))?;
 
         let file_opts = CreateOptions::new()
             .perm(Mode::from_bits_truncate(0o660))
-            .owner(user.uid)
-            .group(user.gid);
+            .owner(uid)
+            .group(gid);
 
         let shmem: SharedMemory<ConfigVersionCacheData> = SharedMemory::open(file_path, file_opts)?;
 
@@ -145,6 +152,22 @@ impl ConfigVersionCache {
             .fetch_add(1, Ordering::AcqRel);
     }
 
+    /// Returns the node config generation number.
+    pub fn node_config_generation(&self) -> usize {
+        self.shmem
+            .data()
+            .node_config_generation
+            .load(Ordering::Acquire)
+    }
+
+    /// Increase the node config generation number.
+    pub fn increase_node_config_generation(&self) {
+        self.shmem
+            .data()
+            .node_config_generation
+            .fetch_add(1, Ordering::AcqRel);
+    }
+
     /// Increase the datastore generation number.
     // FIXME: remove with PBS 3.0 or make actually useful again in datastore lookup
     pub fn increase_datastore_generation(&self) -> usize {
@@ -154,3 +177,21 @@ impl ConfigVersionCache {
             .fetch_add(1, Ordering::AcqRel)
     }
 }
+
+#[test]
+fn test_node_config_generation() -> Result<(), Error> {
+    let path = Path::new(".testdir-config-version-cache/config-versions");
+    let _ = std::fs::remove_file(path);
+
+    let cache = ConfigVersionCache::open_path(path, nix::unistd::getuid(), nix::unistd::getgid())?;
+
+    let start = cache.node_config_generation();
+    for i in 1..=3 {
+        cache.increase_node_config_generation();
+        assert_eq!(cache.node_config_generation(), start + i);
+    }
+
+    std::fs::remove_dir_all(path.parent().unwrap())?;
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! LRU cache for chunks loaded from a datastore, bounded by the stored blob bytes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+
+use crate::DataBlob;
+
+struct CacheEntry {
+    blob: Arc<DataBlob>,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<[u8; 32], CacheEntry>,
+    // access counter value -> digest, least recently used first
+    access_order: BTreeMap<u64, [u8; 32]>,
+    access_counter: u64,
+    stored_bytes: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, digest: &[u8; 32]) -> Option<Arc<DataBlob>> {
+        let entry = self.entries.get_mut(digest)?;
+
+        self.access_order.remove(&entry.last_access);
+        self.access_counter += 1;
+        entry.last_access = self.access_counter;
+        self.access_order.insert(self.access_counter, *digest);
+
+        Some(Arc::clone(&entry.blob))
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let (last_access, digest) = match self.access_order.iter().next() {
+            Some((last_access, digest)) => (*last_access, *digest),
+            None => return false,
+        };
+
+        self.access_order.remove(&last_access);
+        if let Some(entry) = self.entries.remove(&digest) {
+            self.stored_bytes -= entry.blob.raw_size();
+        }
+        true
+    }
+}
+
+/// Cache for chunks loaded with [`DataStore::load_chunk_cached`](crate::DataStore::load_chunk_cached).
+///
+/// The cache is owned by the caller, so its lifetime and size are explicit, e.g. one cache per
+/// verification or restore job. The cache can be shared between threads.
+///
+/// The limit applies to the stored blob bytes, that is the chunks as they are kept in memory,
+/// possibly compressed or encrypted. The decoded data of the cached chunks can be several times
+/// larger. Blobs larger than the whole limit are never cached.
+pub struct ChunkCache {
+    max_stored_bytes: u64,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ChunkCache {
+    /// Creates a new cache holding at most `max_stored_bytes` bytes of stored blobs.
+    pub fn new(max_stored_bytes: u64) -> Self {
+        Self {
+            max_stored_bytes,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of cached chunks.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sum of the stored blob sizes of all cached chunks.
+    pub fn stored_bytes(&self) -> u64 {
+        self.state.lock().unwrap().stored_bytes
+    }
+
+    /// Number of lookups served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which had to load the chunk.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the cached chunk for `digest`, or loads it with `load` and caches it.
+    ///
+    /// The cache is not locked while loading, so concurrent lookups of the same missing chunk
+    /// may load it more than once.
+    pub fn get_or_load<F>(&self, digest: &[u8; 32], load: F) -> Result<Arc<DataBlob>, Error>
+    where
+        F: FnOnce() -> Result<DataBlob, Error>,
+    {
+        if let Some(blob) = self.state.lock().unwrap().touch(digest) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(blob);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let blob = Arc::new(load()?);
+        self.insert(digest, Arc::clone(&blob));
+
+        Ok(blob)
+    }
+
+    fn insert(&self, digest: &[u8; 32], blob: Arc<DataBlob>) {
+        let size = blob.raw_size();
+        if size > self.max_stored_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.entries.contains_key(digest) {
+            return; // loaded concurrently
+        }
+
+        while state.stored_bytes + size > self.max_stored_bytes {
+            if !state.evict_oldest() {
+                break;
+            }
+        }
+
+        state.access_counter += 1;
+        let last_access = state.access_counter;
+        state.access_order.insert(last_access, *digest);
+        state
+            .entries
+            .insert(*digest, CacheEntry { blob, last_access });
+        state.stored_bytes += size;
+    }
+}
@@ -6,12 +6,15 @@ use anyhow::{bail, format_err, Error};
 
 use pbs_api_types::{DatastoreFSyncLevel, GarbageCollectionStatus};
 use proxmox_io::ReadExt;
-use proxmox_sys::fs::{create_dir, create_path, file_type_from_file_stat, CreateOptions};
+use proxmox_sys::fs::{
+    create_dir, create_path, file_read_optional_string, file_type_from_file_stat, CreateOptions,
+};
 use proxmox_sys::process_locker::{
     ProcessLockExclusiveGuard, ProcessLockSharedGuard, ProcessLocker,
 };
 use proxmox_sys::task_log;
 use proxmox_sys::WorkerTaskContext;
+use proxmox_uuid::Uuid;
 
 use crate::file_formats::{
     COMPRESSED_BLOB_MAGIC_1_0, ENCRYPTED_BLOB_MAGIC_1_0, UNCOMPRESSED_BLOB_MAGIC_1_0,
@@ -26,6 +29,16 @@ pub struct ChunkStore {
     mutex: Mutex<()>,
     locker: Option<Arc<Mutex<ProcessLocker>>>,
     sync_level: DatastoreFSyncLevel,
+    id: Option<Uuid>,
+}
+
+/// Progress of [`ChunkStore::sweep_unused_chunks`], reported after each batch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SweepProgress {
+    /// Percentage of chunk directories processed.
+    pub percentage: usize,
+    /// Number of chunk files looked at so far.
+    pub chunk_count: usize,
 }
 
 // TODO: what about sysctl setting vm.vfs_cache_pressure (0 - 100) ?
@@ -73,6 +86,7 @@ impl ChunkStore {
             mutex: Mutex::new(()),
             locker: None,
             sync_level: Default::default(),
+            id: None,
         }
     }
 
@@ -127,6 +141,15 @@ impl ChunkStore {
         let lockfile_path = Self::lockfile_path(&base);
         proxmox_sys::fs::replace_file(lockfile_path, b"", options.clone(), false)?;
 
+        // the id stays the same even if the datastore is renamed or moved to another path
+        let id = Uuid::generate();
+        proxmox_sys::fs::replace_file(
+            Self::id_path(&base),
+            format!("{id}\n").as_bytes(),
+            options.clone(),
+            true,
+        )?;
+
         // create 64*1024 subdirs
         let mut last_percentage = 0;
 
@@ -159,6 +182,30 @@ impl ChunkStore {
         lockfile_path
     }
 
+    fn id_path<P: Into<PathBuf>>(base: P) -> PathBuf {
+        let mut id_path: PathBuf = base.into();
+        id_path.push(".datastore-id");
+        id_path
+    }
+
+    /// Read the datastore id of the chunk store at `base`.
+    ///
+    /// Returns `None` for chunk stores created before ids were introduced, or if the id file is
+    /// malformed (which is logged).
+    pub fn read_id<P: Into<PathBuf>>(base: P) -> Result<Option<Uuid>, Error> {
+        let id_path = Self::id_path(base);
+        match file_read_optional_string(&id_path)? {
+            Some(id) => match id.trim().parse() {
+                Ok(id) => Ok(Some(id)),
+                Err(err) => {
+                    log::warn!("ignoring invalid datastore id in {id_path:?} - {err}");
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
     /// Opens the chunk store with a new process locker.
     ///
     /// Note that this must be used with care, as it's dangerous to create two instances on the
@@ -185,6 +232,9 @@ impl ChunkStore {
 
         let locker = ProcessLocker::new(lockfile_path)?;
 
+        let id = Self::read_id(&base)
+            .map_err(|err| format_err!("unable to open chunk store '{name}' - {err}"))?;
+
         Ok(ChunkStore {
             name: name.to_owned(),
             base,
@@ -192,9 +242,15 @@ impl ChunkStore {
             locker: Some(locker),
             mutex: Mutex::new(()),
             sync_level,
+            id,
         })
     }
 
+    /// The stable id of this chunk store, `None` for stores created without one.
+    pub fn id(&self) -> Option<&Uuid> {
+        self.id.as_ref()
+    }
+
     pub fn touch_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -347,12 +403,20 @@ impl ChunkStore {
         ProcessLocker::oldest_shared_lock(self.locker.clone().unwrap())
     }
 
+    /// Remove all chunks not touched since the mark phase started.
+    ///
+    /// Chunks are processed in batches of `batch_size`. After each batch `progress` is called
+    /// and the worker is checked for abort and shutdown requests, so an aborted sweep always
+    /// stops between two chunks, with `status` accounting for all chunks removed so far. Once
+    /// all chunks are processed, `progress` is called a last time with 100 percent.
     pub fn sweep_unused_chunks(
         &self,
         oldest_writer: i64,
         phase1_start_time: i64,
         status: &mut GarbageCollectionStatus,
+        batch_size: usize,
         worker: &dyn WorkerTaskContext,
+        progress: &mut dyn FnMut(SweepProgress),
     ) -> Result<(), Error> {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -368,17 +432,21 @@ impl ChunkStore {
 
         min_atime -= 300; // add 5 mins gap for safety
 
-        let mut last_percentage = 0;
+        let batch_size = batch_size.max(1);
+        let mut batch_count = 0;
         let mut chunk_count = 0;
 
         for (entry, percentage, bad) in self.get_chunk_iterator()? {
-            if last_percentage != percentage {
-                last_percentage = percentage;
-                task_log!(worker, "processed {}% ({} chunks)", percentage, chunk_count,);
+            if batch_count == batch_size {
+                progress(SweepProgress {
+                    percentage,
+                    chunk_count,
+                });
+                worker.check_abort()?;
+                worker.fail_on_shutdown()?;
+                batch_count = 0;
             }
-
-            worker.check_abort()?;
-            worker.fail_on_shutdown()?;
+            batch_count += 1;
 
             let (dirfd, entry) = match entry {
                 Ok(entry) => (entry.parent_fd(), entry),
@@ -436,6 +504,11 @@ impl ChunkStore {
             drop(lock);
         }
 
+        progress(SweepProgress {
+            percentage: 100,
+            chunk_count,
+        });
+
         Ok(())
     }
 
@@ -610,3 +683,138 @@ fn test_chunk_store1() {
 
     if let Err(_e) = std::fs::remove_dir_all(".testdir") { /* ignore */ }
 }
+
+#[test]
+fn test_sweep_unused_chunks_progress() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct AbortWorker(AtomicBool);
+
+    impl WorkerTaskContext for AbortWorker {
+        fn abort_requested(&self) -> bool {
+            self.0.load(Ordering::SeqCst)
+        }
+
+        fn shutdown_requested(&self) -> bool {
+            false
+        }
+
+        fn log(&self, level: log::Level, message: &std::fmt::Arguments) {
+            println!("{level}: {message}");
+        }
+    }
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-sweep-progress");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap();
+    let chunk_store = ChunkStore::create(
+        "test",
+        &path,
+        user.uid,
+        user.gid,
+        None,
+        DatastoreFSyncLevel::None,
+    )
+    .unwrap();
+
+    let mut digests = Vec::new();
+    for fill in 0u8..4 {
+        let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(&[fill; 64])
+            .build()
+            .unwrap();
+        chunk_store.insert_chunk(&chunk, &digest).unwrap();
+        digests.push(digest);
+    }
+    let chunks_on_disk = || {
+        digests
+            .iter()
+            .filter(|digest| chunk_store.chunk_path(digest).0.exists())
+            .count()
+    };
+
+    // pretend the mark phase started in the future, so every chunk is garbage
+    let start_time = proxmox_time::epoch_i64() + 3 * 24 * 3600;
+
+    // abort after the first batch, everything removed so far must be accounted for
+    let worker = AbortWorker(AtomicBool::new(false));
+    let mut status = GarbageCollectionStatus::default();
+    let mut calls = 0;
+    let result = chunk_store.sweep_unused_chunks(
+        start_time,
+        start_time,
+        &mut status,
+        1,
+        &worker,
+        &mut |_progress| {
+            calls += 1;
+            worker.0.store(true, Ordering::SeqCst);
+        },
+    );
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+    assert_eq!(status.removed_chunks, 1);
+    assert_eq!(chunks_on_disk(), 3);
+
+    // a full run reports after every batch and once at the end
+    let worker = AbortWorker(AtomicBool::new(false));
+    let mut status = GarbageCollectionStatus::default();
+    let mut reports = Vec::new();
+    chunk_store
+        .sweep_unused_chunks(
+            start_time,
+            start_time,
+            &mut status,
+            2,
+            &worker,
+            &mut |progress| reports.push(progress),
+        )
+        .unwrap();
+    assert_eq!(status.removed_chunks, 3);
+    assert_eq!(chunks_on_disk(), 0);
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].chunk_count, 2);
+    assert_eq!(
+        reports[1],
+        SweepProgress {
+            percentage: 100,
+            chunk_count: 3,
+        }
+    );
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_read_invalid_id() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-invalid-id");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap();
+    ChunkStore::create(
+        "test",
+        &path,
+        user.uid,
+        user.gid,
+        None,
+        DatastoreFSyncLevel::None,
+    )
+    .unwrap();
+    assert!(ChunkStore::read_id(&path).unwrap().is_some());
+
+    // a garbled id file must not keep the store from being opened
+    std::fs::write(ChunkStore::id_path(&path), "not-a-uuid\n").unwrap();
+    assert!(ChunkStore::read_id(&path).unwrap().is_none());
+    let chunk_store = ChunkStore::open("test", &path, DatastoreFSyncLevel::None).unwrap();
+    assert!(chunk_store.id().is_none());
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
@@ -3,6 +3,7 @@ use std::io::{self, Write};
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 use lazy_static::lazy_static;
@@ -17,14 +18,16 @@ use proxmox_sys::fs::{lock_dir_noblock, DirLockGuard};
 use proxmox_sys::process_locker::ProcessLockSharedGuard;
 use proxmox_sys::WorkerTaskContext;
 use proxmox_sys::{task_log, task_warn};
+use proxmox_uuid::Uuid;
 
 use pbs_api_types::{
     Authid, BackupNamespace, BackupType, ChunkOrder, DataStoreConfig, DatastoreFSyncLevel,
-    DatastoreTuning, GarbageCollectionStatus, Operation, UPID,
+    DatastoreTuning, GarbageCollectionStatus, Operation, GC_SWEEP_BATCH_SIZE_DEFAULT, UPID,
 };
 
 use crate::backup_info::{BackupDir, BackupGroup};
-use crate::chunk_store::ChunkStore;
+use crate::chunk_cache::ChunkCache;
+use crate::chunk_store::{ChunkStore, SweepProgress};
 use crate::dynamic_index::{DynamicIndexReader, DynamicIndexWriter};
 use crate::fixed_index::{FixedIndexReader, FixedIndexWriter};
 use crate::hierarchy::{ListGroups, ListGroupsType, ListNamespaces, ListNamespacesRecursive};
@@ -49,6 +52,127 @@ pub fn check_backup_owner(owner: &Authid, auth_id: &Authid) -> Result<(), Error>
     Ok(())
 }
 
+/// Interval in which [`lock_dir_timeout`] retries acquiring a busy lock.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Exclusively lock a directory, polling for up to `timeout` if it's currently locked.
+///
+/// Like `lock_dir_noblock`, but a lock held by another operation that's about to finish doesn't
+/// lead to an immediate "already in use" error. A zero `timeout` tries exactly once.
+pub fn lock_dir_timeout(
+    path: &Path,
+    what: &str,
+    would_block_msg: &str,
+    timeout: Duration,
+) -> Result<DirLockGuard, Error> {
+    use nix::fcntl::{flock, FlockArg, OFlag};
+
+    let handle = nix::dir::Dir::open(path, OFlag::O_RDONLY, nix::sys::stat::Mode::empty())
+        .map_err(|err| {
+            format_err!("unable to open {what} directory {path:?} for locking - {err}")
+        })?;
+
+    let start = Instant::now();
+    loop {
+        match flock(handle.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => return Ok(handle),
+            Err(nix::errno::Errno::EWOULDBLOCK) => {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    bail!(
+                        "unable to acquire lock on {what} directory {path:?} - {would_block_msg}"
+                    );
+                }
+                std::thread::sleep(LOCK_POLL_INTERVAL.min(timeout - elapsed));
+            }
+            Err(err) => bail!("unable to acquire lock on {what} directory {path:?} - {err}"),
+        }
+    }
+}
+
+/// Lock a snapshot directory, either failing immediately or waiting up to `timeout` if in use.
+fn lock_snapshot_dir(
+    path: &Path,
+    would_block_msg: &str,
+    timeout: Option<Duration>,
+) -> Result<DirLockGuard, Error> {
+    match timeout {
+        Some(timeout) => lock_dir_timeout(path, "snapshot", would_block_msg, timeout),
+        None => lock_dir_noblock(path, "snapshot", would_block_msg),
+    }
+}
+
+/// Find the datastore config whose chunk store carries the given id.
+///
+/// Only the configured path of each datastore is checked, no other mount points. Datastores
+/// whose maintenance mode refuses `operation` are skipped without touching their path, as that
+/// may be unavailable (e.g. an offline removable disk).
+fn find_datastore_by_id<'a>(
+    configs: &'a [DataStoreConfig],
+    id: &Uuid,
+    operation: Option<Operation>,
+) -> Option<&'a DataStoreConfig> {
+    configs
+        .iter()
+        .filter(|config| match config.get_maintenance_mode() {
+            Some(maintenance_mode) => maintenance_mode.check(operation).is_ok(),
+            None => true,
+        })
+        .find(|config| match ChunkStore::read_id(&config.path) {
+            Ok(store_id) => store_id.as_ref() == Some(id),
+            Err(err) => {
+                log::warn!("skipping datastore '{}' - {err}", config.name);
+                false
+            }
+        })
+}
+
+/// Format version written into the trailer line of the `.gc-status` file.
+const GC_STATUS_FORMAT_VERSION: u32 = 1;
+
+/// Serialize a GC status for the `.gc-status` file.
+///
+/// The JSON is followed by a trailer line containing the format version and a CRC32 of the JSON,
+/// so that a file truncated or garbled by a crash can be told apart from a valid one on load.
+fn encode_gc_status(status: &GarbageCollectionStatus) -> Result<String, Error> {
+    let json = serde_json::to_string(status)?;
+    let crc = crc32fast::hash(json.as_bytes());
+    Ok(format!(
+        "{json}\n#v{GC_STATUS_FORMAT_VERSION} crc32={crc:08x}\n"
+    ))
+}
+
+/// Parse the contents of a `.gc-status` file, verifying the checksum trailer if present.
+///
+/// Files written by older versions contain only the plain JSON and are accepted as is.
+fn decode_gc_status(data: &str) -> Result<GarbageCollectionStatus, Error> {
+    let (json, trailer) = match data.trim_end_matches('\n').rsplit_once('\n') {
+        Some(parts) => parts,
+        None => return Ok(serde_json::from_str(data)?),
+    };
+
+    let (version, crc) = trailer
+        .strip_prefix("#v")
+        .and_then(|trailer| trailer.split_once(" crc32="))
+        .ok_or_else(|| format_err!("invalid trailer '{trailer}'"))?;
+
+    let version: u32 = version
+        .parse()
+        .map_err(|err| format_err!("invalid format version '{version}' - {err}"))?;
+    if version != GC_STATUS_FORMAT_VERSION {
+        bail!("unsupported format version {version}");
+    }
+
+    let crc = u32::from_str_radix(crc, 16)
+        .map_err(|err| format_err!("invalid checksum '{crc}' - {err}"))?;
+    let computed = crc32fast::hash(json.as_bytes());
+    if crc != computed {
+        bail!("checksum mismatch (expected {crc:08x}, got {computed:08x})");
+    }
+
+    Ok(serde_json::from_str(json)?)
+}
+
 /// Datastore Management
 ///
 /// A Datastore can store severals backups, and provides the
@@ -61,6 +185,7 @@ pub struct DataStoreImpl {
     chunk_order: ChunkOrder,
     last_digest: Option<[u8; 32]>,
     sync_level: DatastoreFSyncLevel,
+    gc_sweep_batch_size: usize,
 }
 
 impl DataStoreImpl {
@@ -75,6 +200,7 @@ impl DataStoreImpl {
             chunk_order: Default::default(),
             last_digest: None,
             sync_level: Default::default(),
+            gc_sweep_batch_size: GC_SWEEP_BATCH_SIZE_DEFAULT,
         })
     }
 }
@@ -121,6 +247,27 @@ impl DataStore {
         })
     }
 
+    /// Create and open a fresh datastore in a `.testdir-{name}` directory, only for unit tests.
+    #[cfg(test)]
+    pub(crate) fn new_test_store(name: &str) -> Result<Arc<Self>, Error> {
+        let mut path = std::fs::canonicalize(".")?; // we need absolute path
+        path.push(format!(".testdir-{name}"));
+
+        if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+        let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+        ChunkStore::create(
+            name,
+            &path,
+            user.uid,
+            user.gid,
+            None,
+            DatastoreFSyncLevel::None,
+        )?;
+
+        unsafe { Self::open_path(name, path, None) }
+    }
+
     pub fn lookup_datastore(
         name: &str,
         operation: Option<Operation>,
@@ -131,6 +278,7 @@ impl DataStore {
 
         // we could use the ConfigVersionCache's generation for staleness detection, but  we load
         // the config anyway -> just use digest, additional benefit: manual changes get detected
+        // and lookups don't depend on the version cache's shared memory being accessible at all
         let (config, digest) = pbs_config::datastore::config()?;
         let config: DataStoreConfig = config.lookup("datastore", name)?;
 
@@ -183,6 +331,31 @@ impl DataStore {
         }))
     }
 
+    /// Open a datastore by its stable id instead of its configured name.
+    ///
+    /// This finds the datastore even if its disk got configured under a different name. Only the
+    /// configured paths are checked, so the disk must be mounted at the path of one of the
+    /// configured datastores. Datastores without a valid id file, whose path is currently not
+    /// available, or whose maintenance mode refuses `operation` never match.
+    pub fn open_by_id(id: &Uuid, operation: Option<Operation>) -> Result<Arc<DataStore>, Error> {
+        let (config, _digest) = pbs_config::datastore::config()?;
+        let configs: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
+
+        let name = match find_datastore_by_id(&configs, id, operation) {
+            Some(config) => config.name.clone(),
+            None => bail!("no datastore with id {id} configured"),
+        };
+
+        let datastore = Self::lookup_datastore(&name, operation)?;
+        // the disk could have been swapped since we looked at the id file, and the cached
+        // datastore may still carry the id read when it was first opened
+        if ChunkStore::read_id(datastore.base_path())?.as_ref() != Some(id) {
+            bail!("datastore '{name}' does not have the id {id} anymore");
+        }
+
+        Ok(datastore)
+    }
+
     /// removes all datastores that are not configured anymore
     pub fn remove_unused_datastores() -> Result<(), Error> {
         let (config, _digest) = pbs_config::datastore::config()?;
@@ -252,15 +425,20 @@ impl DataStore {
         let mut gc_status_path = chunk_store.base_path();
         gc_status_path.push(".gc-status");
 
-        let gc_status = if let Some(state) = file_read_optional_string(gc_status_path)? {
-            match serde_json::from_str(&state) {
+        let gc_status = if let Some(state) = file_read_optional_string(&gc_status_path)? {
+            match decode_gc_status(&state) {
                 Ok(state) => state,
                 Err(err) => {
-                    log::error!("error reading gc-status: {}", err);
+                    log::error!(
+                        "corrupt gc-status file {gc_status_path:?} on datastore '{}', last GC \
+                        status is lost - {err}",
+                        config.name,
+                    );
                     GarbageCollectionStatus::default()
                 }
             }
         } else {
+            log::debug!("no gc-status file for datastore '{}' yet", config.name);
             GarbageCollectionStatus::default()
         };
 
@@ -277,6 +455,9 @@ impl DataStore {
             chunk_order: tuning.chunk_order.unwrap_or_default(),
             last_digest,
             sync_level: tuning.sync_level.unwrap_or_default(),
+            gc_sweep_batch_size: tuning
+                .gc_sweep_batch_size
+                .unwrap_or(GC_SWEEP_BATCH_SIZE_DEFAULT),
         })
     }
 
@@ -375,6 +556,11 @@ impl DataStore {
         Ok(())
     }
 
+    /// The stable id of the datastore, `None` for datastores created without one.
+    pub fn id(&self) -> Option<&Uuid> {
+        self.inner.chunk_store.id()
+    }
+
     pub fn name(&self) -> &str {
         self.inner.chunk_store.name()
     }
@@ -556,6 +742,77 @@ impl DataStore {
         backup_group.destroy()
     }
 
+    /// Rename a backup group, for example after the guest it belongs to got a new ID.
+    ///
+    /// The group directory is moved as a whole, so the owner, all snapshots and their protection
+    /// markers stay with it. Moving a group to another namespace or backup type is refused, and
+    /// the destination must not exist yet. The source group needs a valid owner, which is checked
+    /// before anything is moved.
+    ///
+    /// The source group and all its snapshots are locked for the duration of the move, so this
+    /// fails if any of them is currently in use.
+    pub fn rename_group(&self, old: &BackupGroup, new: &BackupGroup) -> Result<(), Error> {
+        if old.datastore().name() != self.name() || new.datastore().name() != self.name() {
+            bail!("cannot rename backup group across datastores");
+        }
+        if old.backup_ns() != new.backup_ns() {
+            bail!(
+                "cannot rename backup group {} to {} - namespace differs",
+                old.group(),
+                new.group()
+            );
+        }
+        if old.backup_type() != new.backup_type() {
+            bail!(
+                "cannot rename backup group {} to {} - backup type differs",
+                old.group(),
+                new.group()
+            );
+        }
+        if old.backup_id() == new.backup_id() {
+            bail!("cannot rename backup group {} onto itself", old.group());
+        }
+
+        let old_path = old.full_group_path();
+        let new_path = new.full_group_path();
+
+        let _group_guard = lock_dir_noblock(&old_path, "backup group", "possible running backup")?;
+        let mut _snapshot_guards = Vec::new();
+        for snapshot in old.iter_snapshots()? {
+            let snapshot = snapshot?;
+            _snapshot_guards.push(lock_dir_noblock(
+                &snapshot.full_path(),
+                "snapshot",
+                "possibly running or in use",
+            )?);
+        }
+
+        // the owner moves along with the group, so refuse groups without a valid one up front
+        if let Err(err) = old.get_owner() {
+            bail!("cannot rename backup group {} - {err}", old.group());
+        }
+
+        log::info!("renaming backup group {old_path:?} to {new_path:?}");
+        if let Err(err) = nix::fcntl::renameat2(
+            None,
+            &old_path,
+            None,
+            &new_path,
+            nix::fcntl::RenameFlags::RENAME_NOREPLACE,
+        ) {
+            if err == nix::errno::Errno::EEXIST {
+                bail!(
+                    "cannot rename backup group {} - {} already exists",
+                    old.group(),
+                    new.group()
+                );
+            }
+            bail!("renaming backup group {old_path:?} to {new_path:?} failed - {err}");
+        }
+
+        Ok(())
+    }
+
     /// Remove a backup directory including all content
     pub fn remove_backup_dir(
         self: &Arc<Self>,
@@ -700,10 +957,14 @@ impl DataStore {
     /// Creates a new backup snapshot inside a BackupGroup
     ///
     /// The BackupGroup directory needs to exist.
+    ///
+    /// With a `lock_timeout` of `None` this fails immediately if the snapshot is in use, otherwise
+    /// it waits up to the given duration for the lock to become available.
     pub fn create_locked_backup_dir(
         &self,
         ns: &BackupNamespace,
         backup_dir: &pbs_api_types::BackupDir,
+        lock_timeout: Option<Duration>,
     ) -> Result<(PathBuf, bool, DirLockGuard), Error> {
         let full_path = self.snapshot_path(ns, backup_dir);
         let relative_path = full_path.strip_prefix(self.base_path()).map_err(|err| {
@@ -713,10 +974,10 @@ impl DataStore {
         })?;
 
         let lock = || {
-            lock_dir_noblock(
+            lock_snapshot_dir(
                 &full_path,
-                "snapshot",
                 "internal error - tried creating snapshot that's already in use",
+                lock_timeout,
             )
         };
 
@@ -891,7 +1152,9 @@ impl DataStore {
                 bail!("unexpected error on datastore traversal: {inner} - {path:?}");
             }
         };
-        for entry in walker.filter_entry(|e| !is_hidden(e)) {
+        // never filter the base directory itself, a datastore located at a hidden path would
+        // otherwise have no index files at all, and GC would sweep every chunk
+        for entry in walker.filter_entry(|e| e.depth() == 0 || !is_hidden(e)) {
             let path = match entry {
                 Ok(entry) => entry.into_path(),
                 Err(err) => {
@@ -911,10 +1174,108 @@ impl DataStore {
         Ok(list)
     }
 
+    /// Opens an index file returned by [`list_images`](Self::list_images).
+    ///
+    /// Returns `None` if the file vanished in the meantime, or is not an index file.
+    fn open_image(&self, img: &Path) -> Result<Option<Box<dyn IndexFile>>, Error> {
+        let file = match std::fs::File::open(img) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("can't open index {} - {}", img.to_string_lossy(), err),
+        };
+
+        let index: Box<dyn IndexFile> = match archive_type(img) {
+            Ok(ArchiveType::FixedIndex) => Box::new(FixedIndexReader::new(file).map_err(|e| {
+                format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
+            })?),
+            Ok(ArchiveType::DynamicIndex) => {
+                Box::new(DynamicIndexReader::new(file).map_err(|e| {
+                    format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
+                })?)
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(index))
+    }
+
+    /// Calls `callback` for every index file of the datastore, logging the progress.
+    ///
+    /// `action` is used in the progress log line, e.g. "marked 10% (..)". Index files which
+    /// vanish during the walk are skipped.
+    fn for_each_image<F>(
+        &self,
+        worker: &dyn WorkerTaskContext,
+        action: &str,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&Path, &dyn IndexFile) -> Result<(), Error>,
+    {
+        let image_list = self.list_images()?;
+        let image_count = image_list.len();
+
+        let mut last_percentage: usize = 0;
+
+        for (i, img) in image_list.into_iter().enumerate() {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if let Some(index) = self.open_image(&img)? {
+                callback(&img, &*index)?;
+            }
+
+            let percentage = (i + 1) * 100 / image_count;
+            if percentage > last_percentage {
+                task_log!(
+                    worker,
+                    "{} {}% ({} of {} index files)",
+                    action,
+                    percentage,
+                    i + 1,
+                    image_count,
+                );
+                last_percentage = percentage;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts how many index files reference each chunk.
+    ///
+    /// This walks all index files like the mark phase of garbage collection, but counts the
+    /// references instead of touching the chunks. A chunk used several times by the same index
+    /// counts once. Chunks not referenced by any index are not included.
+    ///
+    /// Note that the map holds an entry for every referenced chunk of the datastore, so for
+    /// big datastores it can get large (in the order of 50 bytes per chunk).
+    pub fn compute_chunk_refcounts(
+        &self,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<HashMap<[u8; 32], u32>, Error> {
+        let mut refcounts = HashMap::new();
+        let mut index_digests = HashSet::new();
+
+        self.for_each_image(worker, "counted", |_img, index| {
+            index_digests.clear();
+            for pos in 0..index.index_count() {
+                worker.check_abort()?;
+                let digest = index.index_digest(pos).unwrap();
+                if index_digests.insert(*digest) {
+                    *refcounts.entry(*digest).or_insert(0) += 1;
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(refcounts)
+    }
+
     // mark chunks  used by ``index`` as used
-    fn index_mark_used_chunks<I: IndexFile>(
+    fn index_mark_used_chunks(
         &self,
-        index: I,
+        index: &dyn IndexFile,
         file_name: &Path, // only used for error reporting
         status: &mut GarbageCollectionStatus,
         worker: &dyn WorkerTaskContext,
@@ -953,17 +1314,9 @@ impl DataStore {
         status: &mut GarbageCollectionStatus,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
-        let image_list = self.list_images()?;
-        let image_count = image_list.len();
-
-        let mut last_percentage: usize = 0;
-
         let mut strange_paths_count: u64 = 0;
 
-        for (i, img) in image_list.into_iter().enumerate() {
-            worker.check_abort()?;
-            worker.fail_on_shutdown()?;
-
+        self.for_each_image(worker, "marked", |img, index| {
             if let Some(backup_dir_path) = img.parent() {
                 let backup_dir_path = backup_dir_path.strip_prefix(self.base_path())?;
                 if let Some(backup_dir_str) = backup_dir_path.to_str() {
@@ -973,38 +1326,8 @@ impl DataStore {
                 }
             }
 
-            match std::fs::File::open(&img) {
-                Ok(file) => {
-                    if let Ok(archive_type) = archive_type(&img) {
-                        if archive_type == ArchiveType::FixedIndex {
-                            let index = FixedIndexReader::new(file).map_err(|e| {
-                                format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
-                            })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
-                        } else if archive_type == ArchiveType::DynamicIndex {
-                            let index = DynamicIndexReader::new(file).map_err(|e| {
-                                format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
-                            })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
-                        }
-                    }
-                }
-                Err(err) if err.kind() == io::ErrorKind::NotFound => (), // ignore vanished files
-                Err(err) => bail!("can't open index {} - {}", img.to_string_lossy(), err),
-            }
-
-            let percentage = (i + 1) * 100 / image_count;
-            if percentage > last_percentage {
-                task_log!(
-                    worker,
-                    "marked {}% ({} of {} index files)",
-                    percentage,
-                    i + 1,
-                    image_count,
-                );
-                last_percentage = percentage;
-            }
-        }
+            self.index_mark_used_chunks(index, img, status, worker)
+        })?;
 
         if strange_paths_count > 0 {
             task_log!(
@@ -1053,11 +1376,24 @@ impl DataStore {
             self.mark_used_chunks(&mut gc_status, worker)?;
 
             task_log!(worker, "Start GC phase2 (sweep unused chunks)");
+            let mut last_percentage = 0;
             self.inner.chunk_store.sweep_unused_chunks(
                 oldest_writer,
                 phase1_start_time,
                 &mut gc_status,
+                self.inner.gc_sweep_batch_size,
                 worker,
+                &mut |progress: SweepProgress| {
+                    if progress.percentage != last_percentage {
+                        last_percentage = progress.percentage;
+                        task_log!(
+                            worker,
+                            "processed {}% ({} chunks)",
+                            progress.percentage,
+                            progress.chunk_count,
+                        );
+                    }
+                },
             )?;
 
             task_log!(
@@ -1088,9 +1424,7 @@ impl DataStore {
                 HumanByte::from(gc_status.index_data_bytes),
             );
 
-            if gc_status.index_data_bytes > 0 {
-                let comp_per =
-                    (gc_status.disk_bytes as f64 * 100.) / gc_status.index_data_bytes as f64;
+            if let Some(comp_per) = gc_status.on_disk_percentage() {
                 task_log!(
                     worker,
                     "On-Disk usage: {} ({:.2}%)",
@@ -1101,20 +1435,17 @@ impl DataStore {
 
             task_log!(worker, "On-Disk chunks: {}", gc_status.disk_chunks);
 
-            let deduplication_factor = if gc_status.disk_bytes > 0 {
-                (gc_status.index_data_bytes as f64) / (gc_status.disk_bytes as f64)
-            } else {
-                1.0
-            };
-
-            task_log!(worker, "Deduplication factor: {:.2}", deduplication_factor);
+            task_log!(
+                worker,
+                "Deduplication factor: {:.2}",
+                gc_status.deduplication_factor()
+            );
 
-            if gc_status.disk_chunks > 0 {
-                let avg_chunk = gc_status.disk_bytes / (gc_status.disk_chunks as u64);
+            if let Some(avg_chunk) = gc_status.average_chunk_size() {
                 task_log!(worker, "Average chunk size: {}", HumanByte::from(avg_chunk));
             }
 
-            if let Ok(serialized) = serde_json::to_string(&gc_status) {
+            if let Ok(serialized) = encode_gc_status(&gc_status) {
                 let mut path = self.base_path();
                 path.push(".gc-status");
 
@@ -1179,15 +1510,32 @@ impl DataStore {
         })
     }
 
+    /// Loads a chunk through `cache`, so repeated loads of the same chunk are served from memory.
+    pub fn load_chunk_cached(
+        &self,
+        digest: &[u8; 32],
+        cache: &ChunkCache,
+    ) -> Result<Arc<DataBlob>, Error> {
+        cache.get_or_load(digest, || self.load_chunk(digest))
+    }
+
     /// Updates the protection status of the specified snapshot.
-    pub fn update_protection(&self, backup_dir: &BackupDir, protection: bool) -> Result<(), Error> {
+    ///
+    /// If the snapshot is in use, waits up to `lock_timeout` for it to become available, or fails
+    /// immediately if that is `None`.
+    pub fn update_protection(
+        &self,
+        backup_dir: &BackupDir,
+        protection: bool,
+        lock_timeout: Option<Duration>,
+    ) -> Result<(), Error> {
         let full_path = backup_dir.full_path();
 
         if !full_path.exists() {
             bail!("snapshot {} does not exist!", backup_dir.dir());
         }
 
-        let _guard = lock_dir_noblock(&full_path, "snapshot", "possibly running or in use")?;
+        let _guard = lock_snapshot_dir(&full_path, "possibly running or in use", lock_timeout)?;
 
         let protected_path = backup_dir.protected_file();
         if protection {
@@ -1340,6 +1688,15 @@ impl DataStore {
         Ok(())
     }
 
+    /// Returns the number of currently active `(read, write)` operations on a datastore.
+    ///
+    /// These are the counters checked before a datastore can enter maintenance mode, they're read
+    /// while holding the same lock used when updating them.
+    pub fn active_operations(name: &str) -> Result<(i64, i64), Error> {
+        let (operations, _lock) = task_tracking::get_active_operations_locked(name)?;
+        Ok((operations.read, operations.write))
+    }
+
     /// Destroy a datastore. This requires that there are no active operations on the datastore.
     ///
     /// This is a synchronous operation and should be run in a worker-thread.
@@ -1406,8 +1763,17 @@ impl DataStore {
             pbs_config::datastore::save_config(&config)?;
         }
 
-        // finally the lock & toplevel directory
+        // finally the id, the lock & toplevel directory
         if destroy_data {
+            if ok {
+                if let Err(err) = std::fs::remove_file(base.join(".datastore-id")) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        task_warn!(worker, "failed to remove .datastore-id file: {err}");
+                        ok = false;
+                    }
+                }
+            }
+
             if ok {
                 if let Err(err) = std::fs::remove_file(base.join(".lock")) {
                     if err.kind() != io::ErrorKind::NotFound {
@@ -1446,3 +1812,329 @@ impl DataStore {
         Ok(())
     }
 }
+
+#[test]
+fn test_gc_status_roundtrip() -> Result<(), Error> {
+    let status = GarbageCollectionStatus {
+        upid: Some(
+            "UPID:pbs:000001D7:00000B6E:00000000:6537A1F2:garbage_collection:store1:root@pam:"
+                .to_string(),
+        ),
+        index_file_count: 12,
+        index_data_bytes: 1234567,
+        disk_bytes: 4567,
+        disk_chunks: 3,
+        removed_bytes: 100,
+        removed_chunks: 1,
+        ..Default::default()
+    };
+
+    let encoded = encode_gc_status(&status)?;
+    assert!(decode_gc_status(&encoded)? == status);
+
+    // files written by older versions don't have a checksum trailer
+    let legacy = serde_json::to_string(&status)?;
+    assert!(decode_gc_status(&legacy)? == status);
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_status_detect_corruption() -> Result<(), Error> {
+    let status = GarbageCollectionStatus {
+        index_data_bytes: 1234567,
+        disk_bytes: 4567,
+        ..Default::default()
+    };
+
+    let encoded = encode_gc_status(&status)?;
+    let pos = encoded.find("1234567").unwrap() + 6;
+
+    let mut corrupted = encoded.into_bytes();
+    corrupted[pos] ^= 0x01; // '7' -> '6', still valid JSON
+    let corrupted = String::from_utf8(corrupted)?;
+
+    let err = decode_gc_status(&corrupted).unwrap_err();
+    assert!(err.to_string().contains("checksum mismatch"));
+
+    // truncated by a crash while writing
+    assert!(decode_gc_status(&corrupted[..corrupted.len() / 2]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_lock_dir_timeout() -> Result<(), Error> {
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-lock-dir-timeout");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+    std::fs::create_dir_all(&path)?;
+
+    let guard = lock_dir_noblock(&path, "test", "in use")?;
+
+    // no waiting if the holder doesn't release the lock
+    assert!(lock_dir_timeout(&path, "test", "in use", Duration::ZERO).is_err());
+    assert!(lock_dir_timeout(&path, "test", "in use", Duration::from_millis(50)).is_err());
+
+    let holder = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        drop(guard);
+    });
+
+    let _guard = lock_dir_timeout(&path, "test", "in use", Duration::from_secs(10))?;
+    holder.join().unwrap();
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_chunk_cached() -> Result<(), Error> {
+    use crate::data_blob::DataChunkBuilder;
+
+    let store = DataStore::new_test_store("load-chunk-cached")?;
+
+    let mut chunks = Vec::new();
+    for fill in 1u8..=3 {
+        let (chunk, digest) = DataChunkBuilder::new(&[fill; 4096]).build()?;
+        store.insert_chunk(&chunk, &digest)?;
+        chunks.push((chunk.raw_size(), digest));
+    }
+    let ((size_a, a), (size_b, b), (size_c, c)) = (chunks[0], chunks[1], chunks[2]);
+
+    // not enough room for all three chunks
+    let cache = ChunkCache::new(size_a + size_b + size_c - 1);
+
+    let first = store.load_chunk_cached(&a, &cache)?;
+    let second = store.load_chunk_cached(&a, &cache)?;
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+    // served from memory, the file isn't needed anymore
+    std::fs::remove_file(store.chunk_path(&a).0)?;
+    store.load_chunk_cached(&a, &cache)?;
+    assert_eq!((cache.hits(), cache.misses()), (2, 1));
+
+    // loading the other chunks evicts the least recently used one
+    store.load_chunk_cached(&b, &cache)?;
+    store.load_chunk_cached(&c, &cache)?;
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.stored_bytes(), size_b + size_c);
+    assert!(store.load_chunk_cached(&a, &cache).is_err());
+    assert_eq!((cache.hits(), cache.misses()), (2, 4));
+
+    if let Err(_e) = std::fs::remove_dir_all(store.base_path()) { /* ignore */ }
+
+    Ok(())
+}
+
+#[cfg(test)]
+struct TestWorker;
+
+#[cfg(test)]
+impl WorkerTaskContext for TestWorker {
+    fn abort_requested(&self) -> bool {
+        false
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        false
+    }
+
+    fn log(&self, level: log::Level, message: &std::fmt::Arguments) {
+        println!("{level}: {message}");
+    }
+}
+
+#[test]
+fn test_compute_chunk_refcounts() -> Result<(), Error> {
+    use pbs_api_types::{BackupNamespace, BackupType};
+
+    use crate::data_blob::DataChunkBuilder;
+
+    let store = DataStore::new_test_store("chunk-refcounts")?;
+    let group = store.backup_group_from_parts(BackupNamespace::root(), BackupType::Vm, "100");
+
+    let mut digests = Vec::new();
+    for fill in 1u8..=3 {
+        let (chunk, digest) = DataChunkBuilder::new(&[fill; 4096]).build()?;
+        store.insert_chunk(&chunk, &digest)?;
+        digests.push(digest);
+    }
+    let (shared, first, second) = (digests[0], digests[1], digests[2]);
+
+    for (backup_time, chunks) in [
+        (1_600_000_000, vec![shared, first, shared]),
+        (1_600_000_100, vec![shared, second]),
+    ] {
+        let backup_dir = group.backup_dir(backup_time)?;
+        std::fs::create_dir_all(backup_dir.full_path())?;
+
+        let mut path = backup_dir.relative_path();
+        path.push("disk.img.fidx");
+        let mut writer = store.create_fixed_writer(&path, chunks.len() * 4096, 4096)?;
+        for (pos, digest) in chunks.iter().enumerate() {
+            writer.add_digest(pos, digest)?;
+        }
+        writer.close()?;
+    }
+
+    let refcounts = store.compute_chunk_refcounts(&TestWorker)?;
+    assert_eq!(refcounts.len(), 3);
+    assert_eq!(refcounts[&shared], 2);
+    assert_eq!(refcounts[&first], 1);
+    assert_eq!(refcounts[&second], 1);
+
+    if let Err(_e) = std::fs::remove_dir_all(store.base_path()) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_list_images_hidden_base() -> Result<(), Error> {
+    use pbs_api_types::{BackupNamespace, BackupType};
+
+    // test stores live in hidden `.testdir-*` directories
+    let store = DataStore::new_test_store("list-images-hidden")?;
+    assert!(store
+        .base_path()
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with('.'));
+
+    let group = store.backup_group_from_parts(BackupNamespace::root(), BackupType::Vm, "100");
+    let backup_dir = group.backup_dir(1_600_000_000)?;
+    std::fs::create_dir_all(backup_dir.full_path())?;
+
+    let mut path = backup_dir.relative_path();
+    path.push("disk.img.fidx");
+    let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(&[0u8; 4096]).build()?;
+    store.insert_chunk(&chunk, &digest)?;
+    let mut writer = store.create_fixed_writer(&path, 4096, 4096)?;
+    writer.add_digest(0, &digest)?;
+    writer.close()?;
+
+    // hidden entries below the base directory are still skipped
+    let hidden_dir = store.base_path().join(".hidden");
+    std::fs::create_dir(&hidden_dir)?;
+    std::fs::copy(
+        store.base_path().join(&path),
+        hidden_dir.join("disk.img.fidx"),
+    )?;
+
+    assert_eq!(store.list_images()?, vec![store.base_path().join(&path)]);
+
+    if let Err(_e) = std::fs::remove_dir_all(store.base_path()) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_rename_group() -> Result<(), Error> {
+    use pbs_api_types::{BackupNamespace, BackupType};
+
+    let store = DataStore::new_test_store("rename-group")?;
+    let ns = BackupNamespace::root();
+    let auth_id: Authid = "test@pbs".parse()?;
+
+    let old = store.backup_group_from_parts(ns.clone(), BackupType::Vm, "100");
+    let new = store.backup_group_from_parts(ns.clone(), BackupType::Vm, "200");
+
+    let (_owner, guard) = store.create_locked_backup_group(&ns, old.group(), &auth_id)?;
+    drop(guard);
+    for backup_time in [1_600_000_000, 1_600_000_100] {
+        let snapshot = old.backup_dir(backup_time)?;
+        let (_path, _is_new, _guard) =
+            store.create_locked_backup_dir(&ns, snapshot.as_ref(), None)?;
+    }
+    std::fs::File::create(old.backup_dir(1_600_000_100)?.protected_file())?;
+
+    // a held snapshot lock must make the rename fail without touching anything
+    let (_path, _is_new, snapshot_guard) =
+        store.create_locked_backup_dir(&ns, old.backup_dir(1_600_000_000)?.as_ref(), None)?;
+    assert!(store.rename_group(&old, &new).is_err());
+    assert!(old.exists());
+    assert!(!new.exists());
+    drop(snapshot_guard);
+
+    store.rename_group(&old, &new)?;
+    assert!(!old.exists());
+    assert_eq!(new.get_owner()?, auth_id);
+    assert_eq!(new.list_backups()?.len(), 2);
+    assert!(new.backup_dir(1_600_000_100)?.is_protected());
+    assert!(!new.backup_dir(1_600_000_000)?.is_protected());
+
+    // the destination exists now, and cross-type renames are refused
+    let other = store.backup_group_from_parts(ns.clone(), BackupType::Vm, "300");
+    let (_owner, guard) = store.create_locked_backup_group(&ns, other.group(), &auth_id)?;
+    drop(guard);
+    assert!(store.rename_group(&other, &new).is_err());
+    let ct = store.backup_group_from_parts(ns.clone(), BackupType::Ct, "300");
+    assert!(store.rename_group(&other, &ct).is_err());
+    assert!(other.exists());
+
+    // so are renames into another namespace
+    let child_ns = store.create_namespace(&ns, "child".to_string())?;
+    let moved = store.backup_group_from_parts(child_ns, BackupType::Vm, "300");
+    assert!(store.rename_group(&other, &moved).is_err());
+    assert!(other.exists());
+    assert!(!moved.exists());
+
+    // and groups without an owner are left in place
+    let ownerless = store.backup_group_from_parts(ns.clone(), BackupType::Vm, "400");
+    std::fs::create_dir_all(ownerless.full_group_path())?;
+    let target = store.backup_group_from_parts(ns, BackupType::Vm, "500");
+    assert!(store.rename_group(&ownerless, &target).is_err());
+    assert!(ownerless.exists());
+    assert!(!target.exists());
+
+    if let Err(_e) = std::fs::remove_dir_all(store.base_path()) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_by_id() -> Result<(), Error> {
+    let store = DataStore::new_test_store("datastore-id")?;
+    let id = store.id().expect("new datastores have an id");
+    assert_eq!(ChunkStore::read_id(store.base_path())?.as_ref(), Some(id));
+
+    let other = DataStore::new_test_store("datastore-id-other")?;
+    assert_ne!(other.id(), Some(id));
+
+    // the configured names don't need to match the ones used on creation
+    let path = |store: &DataStore| store.base_path().to_str().unwrap().to_owned();
+    let configs = vec![
+        DataStoreConfig::new("unmounted".to_owned(), "/nonexistent/datastore".to_owned()),
+        DataStoreConfig::new("renamed-other".to_owned(), path(&other)),
+        DataStoreConfig::new("renamed".to_owned(), path(&store)),
+    ];
+    let found = find_datastore_by_id(&configs, id, None).expect("datastore not found by id");
+    assert_eq!(found.name, "renamed");
+
+    let unknown = Uuid::generate();
+    assert!(find_datastore_by_id(&configs, &unknown, None).is_none());
+
+    // datastores refusing the operation are skipped, even if their id would match
+    let mut offline = DataStoreConfig::new("offline".to_owned(), path(&store));
+    offline.maintenance_mode = Some("type=offline".to_string());
+    let configs = vec![
+        offline,
+        DataStoreConfig::new("online".to_owned(), path(&store)),
+    ];
+    let found = find_datastore_by_id(&configs, id, Some(Operation::Read))
+        .expect("datastore not found by id");
+    assert_eq!(found.name, "online");
+    let found = find_datastore_by_id(&configs, id, Some(Operation::Lookup))
+        .expect("datastore not found by id");
+    assert_eq!(found.name, "offline");
+
+    for store in [store, other] {
+        if let Err(_e) = std::fs::remove_dir_all(store.base_path()) { /* ignore */ }
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::os::unix::io::RawFd;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -87,6 +88,9 @@ impl ListGroupsType {
         ns: BackupNamespace,
         ty: BackupType,
     ) -> Result<Self, Error> {
+        #[cfg(test)]
+        OPENED_TYPE_DIRS.with(|opened| opened.borrow_mut().push(ty));
+
         Ok(Self {
             dir: proxmox_sys::fs::read_subdir(fd, &store.type_path(&ns, ty))?,
             store,
@@ -144,15 +148,41 @@ pub struct ListGroups {
     ns: BackupNamespace,
     type_fd: proxmox_sys::fs::ReadDir,
     id_state: Option<ListGroupsType>,
+    /// if set, only descend into these backup type directories
+    types: Option<HashSet<BackupType>>,
 }
 
 impl ListGroups {
     pub fn new(store: Arc<DataStore>, ns: BackupNamespace) -> Result<Self, Error> {
+        Self::new_do(store, ns, None)
+    }
+
+    /// Creates a group iterator that only lists groups of the given backup types.
+    ///
+    /// Type directories not contained in `types` are skipped without being opened, so listing
+    /// e.g. only `vm` groups doesn't need to scan potentially huge `host` group trees.
+    pub fn with_types<I>(
+        store: Arc<DataStore>,
+        ns: BackupNamespace,
+        types: I,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = BackupType>,
+    {
+        Self::new_do(store, ns, Some(types.into_iter().collect()))
+    }
+
+    fn new_do(
+        store: Arc<DataStore>,
+        ns: BackupNamespace,
+        types: Option<HashSet<BackupType>>,
+    ) -> Result<Self, Error> {
         Ok(Self {
             type_fd: proxmox_sys::fs::read_subdir(libc::AT_FDCWD, &store.namespace_path(&ns))?,
             store,
             ns,
             id_state: None,
+            types,
         })
     }
 
@@ -200,6 +230,11 @@ impl Iterator for ListGroups {
 
                 if let Ok(name) = entry.file_name().to_str() {
                     if let Ok(group_type) = BackupType::from_str(name) {
+                        if let Some(types) = &self.types {
+                            if !types.contains(&group_type) {
+                                continue; // not interested in this type, don't descend
+                            }
+                        }
                         // found a backup group type, descend into it to scan all IDs in it
                         // by switching to the id-state branch
                         match ListGroupsType::new_at(
@@ -456,3 +491,42 @@ impl Iterator for ListNamespacesRecursive {
         }
     }
 }
+
+#[cfg(test)]
+thread_local! {
+    /// backup types whose directory got opened by a `ListGroupsType` on this thread
+    static OPENED_TYPE_DIRS: std::cell::RefCell<Vec<BackupType>> = Default::default();
+}
+
+#[test]
+fn test_list_groups_with_types() -> Result<(), Error> {
+    let store = DataStore::new_test_store("list-groups-with-types")?;
+    let base = store.base_path();
+
+    for group in ["vm/100", "vm/101", "ct/200", "host/elsa"] {
+        std::fs::create_dir_all(base.join(group))?;
+    }
+
+    OPENED_TYPE_DIRS.with(|opened| opened.borrow_mut().clear());
+
+    let mut ids: Vec<String> = ListGroups::with_types(
+        Arc::clone(&store),
+        BackupNamespace::root(),
+        [BackupType::Vm],
+    )?
+    .map(|group| group.map(|group| group.group().to_string()))
+    .collect::<Result<_, Error>>()?;
+    ids.sort();
+
+    assert_eq!(ids, ["vm/100", "vm/101"]);
+    OPENED_TYPE_DIRS.with(|opened| assert_eq!(*opened.borrow(), [BackupType::Vm]));
+
+    OPENED_TYPE_DIRS.with(|opened| opened.borrow_mut().clear());
+    let count = ListGroups::new(Arc::clone(&store), BackupNamespace::root())?.count();
+    assert_eq!(count, 4);
+    OPENED_TYPE_DIRS.with(|opened| assert_eq!(opened.borrow().len(), 3));
+
+    let _ = std::fs::remove_dir_all(base);
+
+    Ok(())
+}
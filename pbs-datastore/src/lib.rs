@@ -172,6 +172,7 @@ pub mod cached_chunk_reader;
 pub mod catalog;
 pub mod checksum_reader;
 pub mod checksum_writer;
+pub mod chunk_cache;
 pub mod chunk_stat;
 pub mod chunk_store;
 pub mod chunker;
@@ -195,6 +196,7 @@ pub mod fixed_index;
 pub use backup_info::{BackupDir, BackupGroup, BackupInfo};
 pub use checksum_reader::ChecksumReader;
 pub use checksum_writer::ChecksumWriter;
+pub use chunk_cache::ChunkCache;
 pub use chunk_store::ChunkStore;
 pub use chunker::Chunker;
 pub use crypt_reader::CryptReader;
@@ -206,7 +208,7 @@ pub use manifest::BackupManifest;
 pub use store_progress::StoreProgress;
 
 mod datastore;
-pub use datastore::{check_backup_owner, DataStore};
+pub use datastore::{check_backup_owner, lock_dir_timeout, DataStore};
 
 mod hierarchy;
 pub use hierarchy::{
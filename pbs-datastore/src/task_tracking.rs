@@ -2,7 +2,7 @@ use anyhow::Error;
 use libc::pid_t;
 use nix::unistd::Pid;
 use std::iter::Sum;
-use std::path::PathBuf;
+use std::path::Path;
 
 use pbs_api_types::Operation;
 use proxmox_sys::fs::{file_read_optional_string, open_file_locked, replace_file, CreateOptions};
@@ -34,34 +34,33 @@ struct TaskOperations {
     active_operations: ActiveOperationStats,
 }
 
-fn open_lock_file(name: &str) -> Result<(std::fs::File, CreateOptions), Error> {
+fn lock_file_options() -> Result<CreateOptions, Error> {
     let user = pbs_config::backup_user()?;
 
-    let lock_path = PathBuf::from(format!("{}/{}.lock", crate::ACTIVE_OPERATIONS_DIR, name));
-
-    let options = CreateOptions::new()
+    Ok(CreateOptions::new()
         .group(user.gid)
         .owner(user.uid)
-        .perm(nix::sys::stat::Mode::from_bits_truncate(0o660));
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o660)))
+}
+
+fn open_lock_file(dir: &Path, name: &str, options: &CreateOptions) -> Result<std::fs::File, Error> {
+    let lock_path = dir.join(format!("{}.lock", name));
 
     let timeout = std::time::Duration::new(10, 0);
 
-    Ok((
-        open_file_locked(lock_path, timeout, true, options.clone())?,
-        options,
-    ))
+    open_file_locked(lock_path, timeout, true, options.clone())
 }
 
-/// MUST return `Some(file)` when `lock` is `true`.
+/// MUST return `Some(file)` when `lock` is `Some`.
 fn get_active_operations_do(
+    dir: &Path,
     name: &str,
-    lock: bool,
+    lock: Option<&CreateOptions>,
 ) -> Result<(ActiveOperationStats, Option<std::fs::File>), Error> {
-    let path = PathBuf::from(format!("{}/{}", crate::ACTIVE_OPERATIONS_DIR, name));
-    let lock = if lock {
-        Some(open_lock_file(name)?.0)
-    } else {
-        None
+    let path = dir.join(name);
+    let lock = match lock {
+        Some(options) => Some(open_lock_file(dir, name, options)?),
+        None => None,
     };
 
     let data = match file_read_optional_string(path)? {
@@ -81,20 +80,33 @@ fn get_active_operations_do(
 }
 
 pub fn get_active_operations(name: &str) -> Result<ActiveOperationStats, Error> {
-    Ok(get_active_operations_do(name, false)?.0)
+    let dir = Path::new(crate::ACTIVE_OPERATIONS_DIR);
+    Ok(get_active_operations_do(dir, name, None)?.0)
 }
 
 pub fn get_active_operations_locked(
     name: &str,
 ) -> Result<(ActiveOperationStats, std::fs::File), Error> {
-    let (data, lock) = get_active_operations_do(name, true)?;
+    let dir = Path::new(crate::ACTIVE_OPERATIONS_DIR);
+    let (data, lock) = get_active_operations_do(dir, name, Some(&lock_file_options()?))?;
     Ok((data, lock.unwrap()))
 }
 
 pub fn update_active_operations(name: &str, operation: Operation, count: i64) -> Result<(), Error> {
-    let path = PathBuf::from(format!("{}/{}", crate::ACTIVE_OPERATIONS_DIR, name));
+    let dir = Path::new(crate::ACTIVE_OPERATIONS_DIR);
+    update_active_operations_do(dir, name, operation, count, lock_file_options()?)
+}
+
+fn update_active_operations_do(
+    dir: &Path,
+    name: &str,
+    operation: Operation,
+    count: i64,
+    options: CreateOptions,
+) -> Result<(), Error> {
+    let path = dir.join(name);
 
-    let (_lock, options) = open_lock_file(name)?;
+    let _lock = open_lock_file(dir, name, &options)?;
 
     let pid = std::process::id();
     let starttime = procfs::PidStat::read_from_pid(Pid::from_raw(pid as pid_t))?.starttime;
@@ -142,3 +154,31 @@ pub fn update_active_operations(name: &str, operation: Operation, count: i64) ->
         false,
     )
 }
+
+#[test]
+fn test_active_operations_count() -> Result<(), Error> {
+    let dir = std::fs::canonicalize(".")?.join(".testdir-active-operations");
+    if let Err(_e) = std::fs::remove_dir_all(&dir) { /* ignore */ }
+    std::fs::create_dir_all(&dir)?;
+
+    let options = CreateOptions::new();
+
+    update_active_operations_do(&dir, "test", Operation::Read, 1, options.clone())?;
+    update_active_operations_do(&dir, "test", Operation::Write, 1, options.clone())?;
+    update_active_operations_do(&dir, "test", Operation::Read, 1, options.clone())?;
+    update_active_operations_do(&dir, "test", Operation::Lookup, 1, options.clone())?;
+
+    let (stats, lock) = get_active_operations_do(&dir, "test", Some(&options))?;
+    assert_eq!((stats.read, stats.write), (2, 1));
+    drop(lock);
+
+    update_active_operations_do(&dir, "test", Operation::Read, -1, options.clone())?;
+    update_active_operations_do(&dir, "test", Operation::Write, -1, options)?;
+
+    let (stats, _) = get_active_operations_do(&dir, "test", None)?;
+    assert_eq!((stats.read, stats.write), (1, 0));
+
+    if let Err(_e) = std::fs::remove_dir_all(&dir) { /* ignore */ }
+
+    Ok(())
+}
@@ -5,6 +5,7 @@ use std::ffi::OsStr;
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
@@ -39,7 +40,7 @@ use pbs_api_types::{
     BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA,
     MAX_NAMESPACE_DEPTH, NS_MAX_DEPTH_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
     PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ, PRIV_DATASTORE_VERIFY,
-    UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
+    UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA, VERIFICATION_THREADS_SCHEMA,
 };
 use pbs_client::pxar::{create_tar, create_zip};
 use pbs_config::CachedUserInfo;
@@ -763,6 +764,10 @@ pub async fn status(
                 schema: NS_MAX_DEPTH_SCHEMA,
                 optional: true,
             },
+            threads: {
+                schema: VERIFICATION_THREADS_SCHEMA,
+                optional: true,
+            },
         },
     },
     returns: {
@@ -788,10 +793,12 @@ pub fn verify(
     ignore_verified: Option<bool>,
     outdated_after: Option<i64>,
     max_depth: Option<usize>,
+    threads: Option<usize>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let ns = ns.unwrap_or_default();
+    let threads = threads.unwrap_or(1);
 
     let owner_check_required = check_ns_privs_full(
         &store,
@@ -889,6 +896,7 @@ pub fn verify(
                     &mut StoreProgress::new(1),
                     worker.upid(),
                     Some(&move |manifest| verify_filter(ignore_verified, outdated_after, manifest)),
+                    threads,
                 )?
             } else {
                 let owner = if owner_check_required {
@@ -904,6 +912,7 @@ pub fn verify(
                     max_depth,
                     owner,
                     Some(&move |manifest| verify_filter(ignore_verified, outdated_after, manifest)),
+                    threads,
                 )?
             };
             if !failed_dirs.is_empty() {
@@ -2131,7 +2140,8 @@ pub async fn set_protection(
 
         let backup_dir = datastore.backup_dir(ns, backup_dir)?;
 
-        datastore.update_protection(&backup_dir, protected)
+        // protection changes are quick, so wait a bit instead of failing if the snapshot is busy
+        datastore.update_protection(&backup_dir, protected, Some(Duration::from_secs(5)))
     })
     .await?
 }
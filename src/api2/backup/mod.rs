@@ -192,7 +192,7 @@ fn upgrade_to_backup_protocol(
         };
 
         let (path, is_new, snap_guard) =
-            datastore.create_locked_backup_dir(backup_dir.backup_ns(), backup_dir.as_ref())?;
+            datastore.create_locked_backup_dir(backup_dir.backup_ns(), backup_dir.as_ref(), None)?;
         if !is_new {
             bail!("backup directory already exists.");
         }
@@ -149,6 +149,8 @@ pub enum DeletableProperty {
     Ns,
     /// Delete max-depth property, defaulting to full recursion again
     MaxDepth,
+    /// Delete threads property, verifying one snapshot at a time again
+    Threads,
 }
 
 #[api(
@@ -229,6 +231,9 @@ pub fn update_verification_job(
                 DeletableProperty::MaxDepth => {
                     data.max_depth = None;
                 }
+                DeletableProperty::Threads => {
+                    data.threads = None;
+                }
             }
         }
     }
@@ -266,6 +271,9 @@ pub fn update_verification_job(
             data.max_depth = Some(max_depth);
         }
     }
+    if update.threads.is_some() {
+        data.threads = update.threads;
+    }
 
     // check new store and NS
     user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_VERIFY, true)?;
@@ -280,18 +280,17 @@ async fn order_certificate(
     use proxmox_acme::authorization::Status;
     use proxmox_acme::order::Identifier;
 
-    let domains = node_config.acme_domains().try_fold(
-        Vec::<AcmeDomain>::new(),
-        |mut acc, domain| -> Result<_, Error> {
-            let mut domain = domain?;
+    let domains: Vec<AcmeDomain> = node_config
+        .acme_domains()
+        .map(|domain| {
+            let mut domain = domain.clone();
             domain.domain.make_ascii_lowercase();
             if let Some(alias) = &mut domain.alias {
                 alias.make_ascii_lowercase();
             }
-            acc.push(domain);
-            Ok(acc)
-        },
-    )?;
+            domain
+        })
+        .collect();
 
     let get_domain_config = |domain: &str| {
         domains
@@ -875,7 +875,7 @@ fn restore_list_worker(
                         }
 
                         let (_rel_path, is_new, _snap_lock) =
-                            datastore.create_locked_backup_dir(&ns, backup_dir.as_ref())?;
+                            datastore.create_locked_backup_dir(&ns, backup_dir.as_ref(), None)?;
 
                         if !is_new {
                             bail!("snapshot {}/{} already exists", datastore.name(), &snapshot);
@@ -1407,8 +1407,11 @@ fn restore_archive<'a>(
                         );
                     }
 
-                    let (rel_path, is_new, _snap_lock) =
-                        datastore.create_locked_backup_dir(&backup_ns, backup_dir.as_ref())?;
+                    let (rel_path, is_new, _snap_lock) = datastore.create_locked_backup_dir(
+                        &backup_ns,
+                        backup_dir.as_ref(),
+                        None,
+                    )?;
                     let mut path = datastore.base_path();
                     path.push(rel_path);
 
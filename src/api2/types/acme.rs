@@ -19,7 +19,7 @@ use pbs_api_types::{DNS_ALIAS_FORMAT, DNS_NAME_FORMAT, PROXMOX_SAFE_ID_FORMAT};
     },
     default_key: "domain",
 )]
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 /// A domain entry for an ACME certificate.
 pub struct AcmeDomain {
     /// The domain to certify for.
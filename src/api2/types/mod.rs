@@ -120,13 +120,15 @@ fn test_proxmox_user_id_schema() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Values without the 'http://' prefix are still accepted here so that existing configurations
+// keep loading, but updates of the node config require it (see `check_http_proxy`).
 pub const HTTP_PROXY_SCHEMA: Schema =
-    StringSchema::new("HTTP proxy configuration [http://]<host>[:port]")
+    StringSchema::new("HTTP proxy configuration http://[<user>[:<password>]@]<host>[:port]")
         .format(&ApiStringFormat::VerifyFn(|s| {
             proxmox_http::ProxyConfig::parse_proxy_url(s)?;
             Ok(())
         }))
         .min_length(1)
         .max_length(128)
-        .type_text("[http://]<host>[:port]")
+        .type_text("http://[<user>[:<password>]@]<host>[:port]")
         .schema();
@@ -1,8 +1,8 @@
 use nix::dir::Dir;
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 
@@ -13,7 +13,7 @@ use pbs_api_types::{
     SnapshotVerifyState, VerifyState, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_VERIFY, UPID,
 };
 use pbs_datastore::backup_info::{BackupDir, BackupGroup, BackupInfo};
-use pbs_datastore::index::IndexFile;
+use pbs_datastore::index::{ChunkReadInfo, IndexFile};
 use pbs_datastore::manifest::{archive_type, ArchiveType, BackupManifest, FileInfo};
 use pbs_datastore::{DataBlob, DataStore, StoreProgress};
 use proxmox_sys::fs::lock_dir_noblock_shared;
@@ -29,6 +29,8 @@ pub struct VerifyWorker {
     datastore: Arc<DataStore>,
     verified_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
     corrupt_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    // chunks currently loaded and checked for one of the snapshots verified concurrently
+    in_flight_chunks: Mutex<HashSet<[u8; 32]>>,
 }
 
 impl VerifyWorker {
@@ -41,6 +43,34 @@ impl VerifyWorker {
             verified_chunks: Arc::new(Mutex::new(HashSet::with_capacity(16 * 1024))),
             // start with 64 chunks since we assume there are few corrupt ones
             corrupt_chunks: Arc::new(Mutex::new(HashSet::with_capacity(64))),
+            in_flight_chunks: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+/// Removes the chunks marked as in flight by one index verification once it's done, successful
+/// or not.
+struct InFlightGuard<'a> {
+    in_flight_chunks: &'a Mutex<HashSet<[u8; 32]>>,
+    digests: Vec<[u8; 32]>,
+}
+
+impl InFlightGuard<'_> {
+    /// Mark a chunk as in flight, returns false if it already is.
+    fn insert(&mut self, digest: [u8; 32]) -> bool {
+        let inserted = self.in_flight_chunks.lock().unwrap().insert(digest);
+        if inserted {
+            self.digests.push(digest);
+        }
+        inserted
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut in_flight_chunks = self.in_flight_chunks.lock().unwrap();
+        for digest in &self.digests {
+            in_flight_chunks.remove(digest);
         }
     }
 }
@@ -123,6 +153,12 @@ fn verify_index_chunks(
     let verified_chunks2 = Arc::clone(&verify_worker.verified_chunks);
     let errors2 = Arc::clone(&errors);
 
+    // declared before the decoder pool, so that it's dropped after the pool finished all chunks
+    let mut in_flight = InFlightGuard {
+        in_flight_chunks: &verify_worker.in_flight_chunks,
+        digests: Vec::new(),
+    };
+
     let decoder_pool = ParallelHandler::new(
         "verify chunk decoder",
         4,
@@ -200,17 +236,7 @@ fn verify_index_chunks(
             .datastore
             .get_chunks_in_order(&*index, skip_chunk, check_abort)?;
 
-    for (pos, _) in chunk_list {
-        verify_worker.worker.check_abort()?;
-        verify_worker.worker.fail_on_shutdown()?;
-
-        let info = index.chunk_info(pos).unwrap();
-
-        // we must always recheck this here, the parallel worker below alter it!
-        if skip_chunk(&info.digest) {
-            continue; // already verified or marked corrupt
-        }
-
+    let mut load_chunk = |info: &ChunkReadInfo| -> Result<(), Error> {
         match verify_worker.datastore.load_chunk(&info.digest) {
             Err(err) => {
                 verify_worker
@@ -237,6 +263,55 @@ fn verify_index_chunks(
                 decoded_bytes += size;
             }
         }
+        Ok(())
+    };
+
+    // chunks some other snapshot verified concurrently is currently loading
+    let mut deferred = Vec::new();
+
+    for (pos, _) in chunk_list {
+        verify_worker.worker.check_abort()?;
+        verify_worker.worker.fail_on_shutdown()?;
+
+        let info = index.chunk_info(pos).unwrap();
+
+        // we must always recheck this here, the parallel worker below alter it!
+        if skip_chunk(&info.digest) {
+            continue; // already verified or marked corrupt
+        }
+
+        if !in_flight.insert(info.digest) {
+            deferred.push(info);
+            continue;
+        }
+
+        // it might have been finished between the check above and marking it in flight
+        if skip_chunk(&info.digest) {
+            continue;
+        }
+
+        load_chunk(&info)?;
+    }
+
+    for info in deferred {
+        loop {
+            verify_worker.worker.check_abort()?;
+            verify_worker.worker.fail_on_shutdown()?;
+
+            if skip_chunk(&info.digest) {
+                break;
+            }
+
+            // the other verification stopped without checking the chunk, do it ourselves
+            if in_flight.insert(info.digest) {
+                if !skip_chunk(&info.digest) {
+                    load_chunk(&info)?;
+                }
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
     }
 
     decoder_pool.complete()?;
@@ -445,21 +520,22 @@ pub fn verify_backup_dir_with_lock(
     Ok(error_count == 0)
 }
 
-/// Verify all backups inside a backup group
+/// Verify all backups inside a backup group, checking up to `threads` snapshots concurrently
 ///
-/// Errors are logged to the worker log.
+/// All snapshots share the verified and corrupt chunk sets of `verify_worker`, so chunks used by
+/// multiple snapshots are only read once. Errors are logged to the worker log.
 ///
 /// Returns
-/// - Ok((count, failed_dirs)) where failed_dirs had verification errors
+/// - Ok(failed_dirs) where failed_dirs had verification errors
 /// - Err(_) if task was aborted
 pub fn verify_backup_group(
     verify_worker: &VerifyWorker,
     group: &BackupGroup,
     progress: &mut StoreProgress,
     upid: &UPID,
-    filter: Option<&dyn Fn(&BackupManifest) -> bool>,
+    filter: Option<&(dyn Fn(&BackupManifest) -> bool + Sync)>,
+    threads: usize,
 ) -> Result<Vec<String>, Error> {
-    let mut errors = Vec::new();
     let mut list = match group.list_backups() {
         Ok(list) => list,
         Err(err) => {
@@ -470,39 +546,93 @@ pub fn verify_backup_group(
                 group.group(),
                 err,
             );
-            return Ok(errors);
+            return Ok(Vec::new());
         }
     };
 
     let snapshot_count = list.len();
+    let threads = threads.clamp(1, snapshot_count.max(1));
     task_log!(
         verify_worker.worker,
-        "verify group {}:{} ({} snapshots)",
+        "verify group {}:{} ({} snapshots, {} threads)",
         verify_worker.datastore.name(),
         group.group(),
-        snapshot_count
+        snapshot_count,
+        threads,
     );
 
     progress.group_snapshots = snapshot_count as u64;
+    progress.done_snapshots = 0;
 
     BackupInfo::sort_list(&mut list, false); // newest first
-    for (pos, info) in list.into_iter().enumerate() {
-        if !verify_backup_dir(verify_worker, &info.backup_dir, upid.clone(), filter)? {
-            errors.push(print_ns_and_snapshot(
-                info.backup_dir.backup_ns(),
-                info.backup_dir.as_ref(),
-            ));
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let progress = Mutex::new(progress);
+    let errors = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| -> Result<(), Error> {
+                    while !stop.load(Ordering::SeqCst) {
+                        let pos = next.fetch_add(1, Ordering::SeqCst);
+                        let info = match list.get(pos) {
+                            Some(info) => info,
+                            None => break,
+                        };
+
+                        let result = verify_backup_dir(
+                            verify_worker,
+                            &info.backup_dir,
+                            upid.clone(),
+                            filter.map(|filter| filter as &dyn Fn(&BackupManifest) -> bool),
+                        );
+                        match result {
+                            Ok(true) => (),
+                            Ok(false) => errors.lock().unwrap().push((pos, &info.backup_dir)),
+                            Err(err) => {
+                                // let the other threads stop after their current snapshot
+                                stop.store(true, Ordering::SeqCst);
+                                return Err(err);
+                            }
+                        }
+
+                        let mut progress = progress.lock().unwrap();
+                        progress.done_snapshots += 1;
+                        task_log!(verify_worker.worker, "percentage done: {}", progress);
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        // join all threads before returning, an abort is observed by each of them
+        let mut result = Ok(());
+        for handle in handles {
+            let thread_result = handle
+                .join()
+                .unwrap_or_else(|_| Err(format_err!("verify thread panicked")));
+            if result.is_ok() {
+                result = thread_result;
+            }
         }
-        progress.done_snapshots = pos as u64 + 1;
-        task_log!(verify_worker.worker, "percentage done: {}", progress);
-    }
+        result
+    })?;
 
-    Ok(errors)
+    let mut errors = errors.into_inner().unwrap();
+    errors.sort_unstable_by_key(|(pos, _)| *pos);
+
+    Ok(errors
+        .into_iter()
+        .map(|(_, backup_dir)| print_ns_and_snapshot(backup_dir.backup_ns(), backup_dir.as_ref()))
+        .collect())
 }
 
 /// Verify all (owned) backups inside a datastore
 ///
-/// Errors are logged to the worker log.
+/// Groups are verified one after the other, see [`verify_backup_group`] for `threads`. Errors are
+/// logged to the worker log.
 ///
 /// Returns
 /// - Ok(failed_dirs) where failed_dirs had verification errors
@@ -513,7 +643,8 @@ pub fn verify_all_backups(
     ns: BackupNamespace,
     max_depth: Option<usize>,
     owner: Option<&Authid>,
-    filter: Option<&dyn Fn(&BackupManifest) -> bool>,
+    filter: Option<&(dyn Fn(&BackupManifest) -> bool + Sync)>,
+    threads: usize,
 ) -> Result<Vec<String>, Error> {
     let mut errors = Vec::new();
     let worker = Arc::clone(&verify_worker.worker);
@@ -581,7 +712,7 @@ pub fn verify_all_backups(
         progress.group_snapshots = 0;
 
         let mut group_errors =
-            verify_backup_group(verify_worker, &group, &mut progress, upid, filter)?;
+            verify_backup_group(verify_worker, &group, &mut progress, upid, filter, threads)?;
         errors.append(&mut group_errors);
     }
 
@@ -2,16 +2,21 @@ use anyhow::{bail, Error};
 use serde_json::Value;
 
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
-use proxmox_schema::api;
+use proxmox_schema::{api, ObjectSchemaType, Schema};
 use proxmox_section_config::SectionConfigData;
 
 use pbs_config::drive::{complete_changer_name, complete_drive_name};
 
-use pbs_api_types::CHANGER_NAME_SCHEMA;
+use pbs_api_types::{
+    ScsiTapeChanger, ScsiTapeChangerUpdater, CHANGER_NAME_SCHEMA, PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
 
-use pbs_tape::linux_list_drives::complete_changer_path;
+use pbs_tape::linux_list_drives::{complete_changer_path, linux_tape_changer_list};
 
-use proxmox_backup::{api2, tape::drive::media_changer};
+use proxmox_backup::{
+    api2::{self, config::changer::DeletableProperty},
+    tape::drive::media_changer,
+};
 
 pub fn lookup_changer_name(param: &Value, config: &SectionConfigData) -> Result<String, Error> {
     if let Some(name) = param["name"].as_str() {
@@ -29,6 +34,124 @@ pub fn lookup_changer_name(param: &Value, config: &SectionConfigData) -> Result<
     bail!("unable to get (default) changer name");
 }
 
+/// Returns the property schema of the table rows for an API return schema.
+fn row_schema(schema: &'static Schema) -> Result<&'static dyn ObjectSchemaType, Error> {
+    Ok(match schema {
+        Schema::Array(schema) => return row_schema(schema.items),
+        Schema::Object(schema) => schema,
+        Schema::AllOf(schema) => schema,
+        _ => bail!("invalid schema for table output, must be an object schema"),
+    })
+}
+
+/// Parses a comma separated list of column names, checking them against the row schema.
+fn parse_column_list(list: &str, schema: &'static Schema) -> Result<Vec<String>, Error> {
+    let schema = row_schema(schema)?;
+
+    let mut columns = Vec::new();
+    for column in list.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        if schema.lookup(column).is_none() {
+            let known: Vec<&str> = schema.properties().map(|(name, _, _)| *name).collect();
+            bail!(
+                "unknown column '{column}' (known columns: {})",
+                known.join(", ")
+            );
+        }
+        columns.push(column.to_string());
+    }
+
+    if columns.is_empty() {
+        bail!("no columns specified");
+    }
+
+    Ok(columns)
+}
+
+/// Takes the `columns` parameter out of `param`, so it's not passed on to the API handler.
+///
+/// Returns the selected columns in the given order, or `defaults` if no selection was made.
+fn take_columns(
+    param: &mut Value,
+    defaults: &[&str],
+    schema: &'static Schema,
+) -> Result<Vec<String>, Error> {
+    match param
+        .as_object_mut()
+        .and_then(|param| param.remove("columns"))
+    {
+        Some(Value::String(list)) => parse_column_list(&list, schema),
+        _ => Ok(defaults.iter().map(|column| column.to_string()).collect()),
+    }
+}
+
+/// Checks that `path` looks like a device node below `/dev/`.
+fn check_changer_path_syntax(path: &str) -> Result<(), Error> {
+    let device = match path.strip_prefix("/dev/") {
+        Some(device) => device,
+        None => bail!("invalid changer path '{path}' - expected a device below '/dev/'"),
+    };
+
+    if device.is_empty() || device.ends_with('/') {
+        bail!("invalid changer path '{path}' - missing device name");
+    }
+
+    if device
+        .split('/')
+        .any(|c| c.is_empty() || c == "." || c == "..")
+    {
+        bail!("invalid changer path '{path}' - contains empty or relative path components");
+    }
+
+    Ok(())
+}
+
+/// Checks that `path` is a valid changer device path and refers to an existing changer.
+///
+/// Uses the same device list as the `path` completion.
+fn check_changer_path(path: &str) -> Result<(), Error> {
+    check_changer_path_syntax(path)?;
+
+    let changers = linux_tape_changer_list();
+    if !changers.iter().any(|changer| changer.path == path) {
+        let known: Vec<&str> = changers
+            .iter()
+            .map(|changer| changer.path.as_str())
+            .collect();
+        if known.is_empty() {
+            bail!("path '{path}' is not a tape changer device (no changers detected)");
+        }
+        bail!(
+            "path '{path}' is not a tape changer device (detected changers: {})",
+            known.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that `list` is a comma separated list of positive slot numbers.
+fn check_export_slots(list: &str) -> Result<(), Error> {
+    if list.trim().is_empty() {
+        return Ok(()); // clears the list on update
+    }
+
+    for (i, slot) in list.split(',').enumerate() {
+        let slot = slot.trim();
+        if slot.is_empty() {
+            bail!("invalid export-slots '{list}' - entry {} is empty", i + 1);
+        }
+        match slot.parse::<u64>() {
+            Ok(0) => bail!("invalid export-slots '{list}' - slot numbers start at 1"),
+            Ok(_) => (),
+            Err(_) => {
+                bail!("invalid export-slots '{list}' - '{slot}' is not a positive integer")
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub fn changer_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("scan", CliCommand::new(&API_METHOD_SCAN_FOR_CHANGERS))
@@ -47,14 +170,14 @@ pub fn changer_commands() -> CommandLineInterface {
         )
         .insert(
             "create",
-            CliCommand::new(&api2::config::changer::API_METHOD_CREATE_CHANGER)
+            CliCommand::new(&API_METHOD_CREATE_CHANGER)
                 .arg_param(&["name"])
                 .completion_cb("name", complete_drive_name)
                 .completion_cb("path", complete_changer_path),
         )
         .insert(
             "update",
-            CliCommand::new(&api2::config::changer::API_METHOD_UPDATE_CHANGER)
+            CliCommand::new(&API_METHOD_UPDATE_CHANGER)
                 .arg_param(&["name"])
                 .completion_cb("name", complete_changer_name)
                 .completion_cb("path", complete_changer_path),
@@ -75,6 +198,79 @@ pub fn changer_commands() -> CommandLineInterface {
     cmd_def.into()
 }
 
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: ScsiTapeChanger,
+                flatten: true,
+            },
+        },
+    },
+)]
+/// Create a new changer device
+fn create_changer(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+    if let Some(path) = param["path"].as_str() {
+        check_changer_path(path)?;
+    }
+    if let Some(export_slots) = param["export-slots"].as_str() {
+        check_export_slots(export_slots)?;
+    }
+
+    let info = &api2::config::changer::API_METHOD_CREATE_CHANGER;
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: CHANGER_NAME_SCHEMA,
+            },
+            update: {
+                type: ScsiTapeChangerUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                },
+            },
+            digest: {
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Update a tape changer configuration
+fn update_changer(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+    if let Some(path) = param["path"].as_str() {
+        check_changer_path(path)?;
+    }
+    if let Some(export_slots) = param["export-slots"].as_str() {
+        check_export_slots(export_slots)?;
+    }
+
+    let info = &api2::config::changer::API_METHOD_UPDATE_CHANGER;
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -82,24 +278,32 @@ pub fn changer_commands() -> CommandLineInterface {
                 schema: OUTPUT_FORMAT,
                 optional: true,
             },
+            columns: {
+                description: "Comma separated list of columns to show, in the given order.",
+                type: String,
+                optional: true,
+            },
         },
     },
 )]
 /// List changers
-fn list_changers(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+fn list_changers(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
     let output_format = get_output_format(&param);
     let info = &api2::tape::changer::API_METHOD_LIST_CHANGERS;
+    let columns = take_columns(
+        &mut param,
+        &["name", "path", "vendor", "model", "serial"],
+        info.returns.schema,
+    )?;
     let mut data = match info.handler {
         ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
         _ => unreachable!(),
     };
 
-    let options = default_table_format_options()
-        .column(ColumnConfig::new("name"))
-        .column(ColumnConfig::new("path"))
-        .column(ColumnConfig::new("vendor"))
-        .column(ColumnConfig::new("model"))
-        .column(ColumnConfig::new("serial"));
+    let mut options = default_table_format_options();
+    for column in columns {
+        options = options.column(ColumnConfig::new(&column));
+    }
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
 
@@ -146,23 +350,32 @@ fn scan_for_changers(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<()
             name: {
                 schema: CHANGER_NAME_SCHEMA,
             },
+            columns: {
+                description: "Comma separated list of columns to show, in the given order.",
+                type: String,
+                optional: true,
+            },
         },
     },
 )]
 /// Get tape changer configuration
-fn get_config(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+fn get_config(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
     let output_format = get_output_format(&param);
     let info = &api2::config::changer::API_METHOD_GET_CONFIG;
+    let columns = take_columns(
+        &mut param,
+        &["name", "path", "eject-before-unload", "export-slots"],
+        info.returns.schema,
+    )?;
     let mut data = match info.handler {
         ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
         _ => unreachable!(),
     };
 
-    let options = default_table_format_options()
-        .column(ColumnConfig::new("name"))
-        .column(ColumnConfig::new("path"))
-        .column(ColumnConfig::new("eject-before-unload"))
-        .column(ColumnConfig::new("export-slots"));
+    let mut options = default_table_format_options();
+    for column in columns {
+        options = options.column(ColumnConfig::new(&column));
+    }
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
 
@@ -186,6 +399,17 @@ fn get_config(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error
                 optional: true,
                 default: true,
             },
+            columns: {
+                description: "Comma separated list of columns to show, in the given order.",
+                type: String,
+                optional: true,
+            },
+            "sort-by": {
+                description: "Comma separated list of columns to sort by \
+                    (default: entry-kind,entry-id).",
+                type: String,
+                optional: true,
+            },
         },
     },
 )]
@@ -197,6 +421,15 @@ async fn get_status(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result
 
     let output_format = get_output_format(&param);
     let info = &api2::tape::changer::API_METHOD_GET_STATUS;
+    let columns = take_columns(
+        &mut param,
+        &["entry-kind", "entry-id", "label-text", "loaded-slot"],
+        info.returns.schema,
+    )?;
+    let sort_by = match param.as_object_mut().unwrap().remove("sort-by") {
+        Some(Value::String(list)) => parse_column_list(&list, info.returns.schema)?,
+        _ => vec!["entry-kind".to_string(), "entry-id".to_string()],
+    };
     let mut data = match info.handler {
         ApiHandler::Async(handler) => (handler)(param, info, rpcenv).await?,
         _ => unreachable!(),
@@ -214,13 +447,16 @@ async fn get_status(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result
         }
     };
 
-    let options = default_table_format_options()
-        .sortby("entry-kind", false)
-        .sortby("entry-id", false)
-        .column(ColumnConfig::new("entry-kind"))
-        .column(ColumnConfig::new("entry-id"))
-        .column(ColumnConfig::new("label-text").renderer(render_label_text))
-        .column(ColumnConfig::new("loaded-slot"));
+    let mut options = default_table_format_options();
+    for column in sort_by {
+        options = options.sortby(&column, false);
+    }
+    for column in columns {
+        options = options.column(match column.as_str() {
+            "label-text" => ColumnConfig::new(&column).renderer(render_label_text),
+            _ => ColumnConfig::new(&column),
+        });
+    }
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
 
@@ -261,3 +497,53 @@ pub async fn transfer(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Resu
 
     Ok(())
 }
+
+#[test]
+fn test_column_selection() -> Result<(), Error> {
+    let schema = api2::tape::changer::API_METHOD_LIST_CHANGERS.returns.schema;
+
+    assert_eq!(
+        parse_column_list("serial, name", schema)?,
+        ["serial", "name"]
+    );
+    assert!(parse_column_list("name,no-such-column", schema).is_err());
+    assert!(parse_column_list(" , ", schema).is_err());
+
+    let mut param = serde_json::json!({ "columns": "vendor" });
+    assert_eq!(take_columns(&mut param, &["name"], schema)?, ["vendor"]);
+    assert!(param.get("columns").is_none());
+    assert_eq!(take_columns(&mut param, &["name"], schema)?, ["name"]);
+
+    let mut param = serde_json::json!({ "columns": "name,foo" });
+    assert!(take_columns(&mut param, &["name"], schema).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_export_slots() {
+    assert!(check_export_slots("1").is_ok());
+    assert!(check_export_slots("1,2, 10").is_ok());
+    assert!(check_export_slots("").is_ok());
+
+    assert!(check_export_slots("0").is_err());
+    assert!(check_export_slots("1,,2").is_err());
+    assert!(check_export_slots("1,2,").is_err());
+    assert!(check_export_slots("-1").is_err());
+    assert!(check_export_slots("1;2").is_err());
+    assert!(check_export_slots("1-3").is_err());
+    assert!(check_export_slots("one").is_err());
+}
+
+#[test]
+fn test_check_changer_path_syntax() {
+    assert!(check_changer_path_syntax("/dev/sg4").is_ok());
+    assert!(check_changer_path_syntax("/dev/tape/by-id/scsi-CC2C52").is_ok());
+
+    assert!(check_changer_path_syntax("sg4").is_err());
+    assert!(check_changer_path_syntax("/tmp/sg4").is_err());
+    assert!(check_changer_path_syntax("/dev/").is_err());
+    assert!(check_changer_path_syntax("/dev//sg4").is_err());
+    assert!(check_changer_path_syntax("/dev/../etc/passwd").is_err());
+    assert!(check_changer_path_syntax("/dev/tape/").is_err());
+}
@@ -1,12 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
 use anyhow::{bail, Error};
 use openssl::ssl::{SslAcceptor, SslMethod};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use proxmox_schema::{api, ApiStringFormat, ApiType, Updater};
+use proxmox_schema::{api, ApiStringFormat, ApiType, ObjectSchema, Schema, Updater};
 
 use proxmox_http::ProxyConfig;
+use proxmox_human_byte::HumanByte;
 
 use pbs_api_types::{
     EMAIL_SCHEMA, MULTI_LINE_COMMENT_SCHEMA, OPENSSL_CIPHERS_TLS_1_2_SCHEMA,
@@ -14,12 +18,10 @@ use pbs_api_types::{
 };
 
 use pbs_buildcfg::configdir;
-use pbs_config::{open_backup_lockfile, BackupLockGuard};
+use pbs_config::{open_backup_lockfile, BackupLockGuard, ConfigVersionCache};
 
 use crate::acme::AcmeClient;
-use crate::api2::types::{
-    AcmeAccountName, AcmeDomain, ACME_DOMAIN_PROPERTY_SCHEMA, HTTP_PROXY_SCHEMA,
-};
+use crate::api2::types::{AcmeAccountName, AcmeDomain, HTTP_PROXY_SCHEMA};
 
 const CONF_FILE: &str = configdir!("/node.cfg");
 const LOCK_FILE: &str = configdir!("/.node.lck");
@@ -40,10 +42,32 @@ pub fn config() -> Result<(NodeConfig, [u8; 32]), Error> {
 
 /// Write the Node Config, requires the write lock to be held.
 pub fn save_config(config: &NodeConfig) -> Result<(), Error> {
+    save_config_impl(
+        config,
+        |raw| pbs_config::replace_backup_config(CONF_FILE, raw),
+        ConfigVersionCache::new,
+    )
+}
+
+// Actual work of `save_config`, with the file write and the version cache injectable for tests.
+fn save_config_impl(
+    config: &NodeConfig,
+    write: impl FnOnce(&[u8]) -> Result<(), Error>,
+    version_cache: impl FnOnce() -> Result<Arc<ConfigVersionCache>, Error>,
+) -> Result<(), Error> {
     config.validate()?;
 
     let raw = crate::tools::config::to_bytes(config, &NodeConfig::API_SCHEMA)?;
-    pbs_config::replace_backup_config(CONF_FILE, &raw)
+    write(&raw)?;
+
+    // increase node config version, so other daemons notice the change without polling the
+    // file - the config is saved already, so failing here must not fail the update
+    match version_cache() {
+        Ok(version_cache) => version_cache.increase_node_config_generation(),
+        Err(err) => log::error!("unable to increase node config generation - {err}"),
+    }
+
+    Ok(())
 }
 
 #[api(
@@ -60,6 +84,88 @@ pub struct AcmeConfig {
     account: AcmeAccountName,
 }
 
+/// The ACME domains of a node.
+///
+/// In the config file (and the API) every domain is stored as a numbered `acmedomainN`
+/// property string, as it always was, but the number of domains is not limited. Entries are
+/// ordered by their number when reading and get renumbered from 0 when writing.
+#[derive(Default)]
+pub struct AcmeDomainList(Vec<AcmeDomain>);
+
+impl ApiType for AcmeDomainList {
+    const API_SCHEMA: Schema = ObjectSchema::new(
+        "ACME domain configuration strings, as 'acmedomainN' properties.",
+        &[],
+    )
+    .additional_properties(true)
+    .schema();
+}
+
+impl From<Vec<AcmeDomain>> for AcmeDomainList {
+    fn from(list: Vec<AcmeDomain>) -> Self {
+        Self(list)
+    }
+}
+
+impl Deref for AcmeDomainList {
+    type Target = Vec<AcmeDomain>;
+
+    fn deref(&self) -> &Vec<AcmeDomain> {
+        &self.0
+    }
+}
+
+impl DerefMut for AcmeDomainList {
+    fn deref_mut(&mut self) -> &mut Vec<AcmeDomain> {
+        &mut self.0
+    }
+}
+
+fn acme_domain_to_property_string(domain: &AcmeDomain) -> String {
+    let mut data = domain.domain.clone();
+    if let Some(alias) = &domain.alias {
+        let _ = write!(data, ",alias={alias}");
+    }
+    if let Some(plugin) = &domain.plugin {
+        let _ = write!(data, ",plugin={plugin}");
+    }
+    data
+}
+
+impl Serialize for AcmeDomainList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().enumerate().map(|(index, domain)| {
+            (
+                format!("acmedomain{index}"),
+                acme_domain_to_property_string(domain),
+            )
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for AcmeDomainList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let mut list = Vec::new();
+        for (key, value) in HashMap::<String, String>::deserialize(deserializer)? {
+            let index: usize = key
+                .strip_prefix("acmedomain")
+                .and_then(|index| index.parse().ok())
+                .ok_or_else(|| D::Error::custom(format!("unknown property '{key}'")))?;
+
+            let domain: AcmeDomain =
+                crate::tools::config::from_property_string(&value, &AcmeDomain::API_SCHEMA)
+                    .map_err(|err| D::Error::custom(format!("invalid {key} - {err}")))?;
+
+            list.push((index, domain));
+        }
+        list.sort_by_key(|(index, _)| *index);
+
+        Ok(Self(list.into_iter().map(|(_, domain)| domain).collect()))
+    }
+}
+
 /// All available languages in Proxmox. Taken from proxmox-i18n repository.
 /// pt_BR, zh_CN, and zh_TW use the same case in the translation files.
 // TODO: auto-generate from available translations
@@ -131,25 +237,8 @@ pub enum Translation {
             type: String,
             format: &ApiStringFormat::PropertyString(&AcmeConfig::API_SCHEMA),
         },
-        acmedomain0: {
-            schema: ACME_DOMAIN_PROPERTY_SCHEMA,
-            optional: true,
-        },
-        acmedomain1: {
-            schema: ACME_DOMAIN_PROPERTY_SCHEMA,
-            optional: true,
-        },
-        acmedomain2: {
-            schema: ACME_DOMAIN_PROPERTY_SCHEMA,
-            optional: true,
-        },
-        acmedomain3: {
-            schema: ACME_DOMAIN_PROPERTY_SCHEMA,
-            optional: true,
-        },
-        acmedomain4: {
-            schema: ACME_DOMAIN_PROPERTY_SCHEMA,
-            optional: true,
+        acme_domains: {
+            type: AcmeDomainList,
         },
         "http-proxy": {
             schema: HTTP_PROXY_SCHEMA,
@@ -174,7 +263,11 @@ pub enum Translation {
         "description" : {
             optional: true,
             schema: MULTI_LINE_COMMENT_SCHEMA,
-        }
+        },
+        "default-bwlimit": {
+            type: HumanByte,
+            optional: true,
+        },
     },
 )]
 #[derive(Deserialize, Serialize, Updater)]
@@ -185,20 +278,10 @@ pub struct NodeConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub acme: Option<String>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub acmedomain0: Option<String>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub acmedomain1: Option<String>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub acmedomain2: Option<String>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub acmedomain3: Option<String>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub acmedomain4: Option<String>,
+    /// The domains to acquire ACME certificates for.
+    #[serde(flatten)]
+    #[updater(skip)]
+    pub acme_domains: AcmeDomainList,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub http_proxy: Option<String>,
@@ -225,6 +308,11 @@ pub struct NodeConfig {
     /// Maximum days to keep Task logs
     #[serde(skip_serializing_if = "Option::is_none")]
     pub task_log_max_days: Option<usize>,
+
+    /// Default upload rate limit (bytes per second) for each client connection not matching any
+    /// traffic control rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_bwlimit: Option<HumanByte>,
 }
 
 impl NodeConfig {
@@ -243,8 +331,31 @@ impl NodeConfig {
         AcmeClient::load(&account).await
     }
 
-    pub fn acme_domains(&self) -> AcmeDomainIter {
-        AcmeDomainIter::new(self)
+    pub fn acme_domains(&self) -> std::slice::Iter<AcmeDomain> {
+        self.acme_domains.iter()
+    }
+
+    /// Adds `domain`, or replaces the entry with the same domain name.
+    pub fn set_acme_domain(&mut self, domain: AcmeDomain) {
+        match self
+            .acme_domains
+            .iter_mut()
+            .find(|entry| entry.domain.eq_ignore_ascii_case(&domain.domain))
+        {
+            Some(entry) => *entry = domain,
+            None => self.acme_domains.push(domain),
+        }
+    }
+
+    /// Removes the entry for the domain `name`.
+    pub fn remove_acme_domain(&mut self, name: &str) -> Result<(), Error> {
+        let count = self.acme_domains.len();
+        self.acme_domains
+            .retain(|entry| !entry.domain.eq_ignore_ascii_case(name));
+        if self.acme_domains.len() == count {
+            bail!("no such ACME domain '{name}'");
+        }
+        Ok(())
     }
 
     /// Returns the parsed ProxyConfig
@@ -268,11 +379,15 @@ impl NodeConfig {
     pub fn validate(&self) -> Result<(), Error> {
         let mut domains = HashSet::new();
         for domain in self.acme_domains() {
-            let domain = domain?;
             if !domains.insert(domain.domain.to_lowercase()) {
                 bail!("duplicate domain '{}' in ACME config", domain.domain);
             }
         }
+        if let Some(limit) = &self.default_bwlimit {
+            if limit.as_u64() == 0 {
+                bail!("default-bwlimit must be a positive byte rate");
+            }
+        }
         let mut dummy_acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
         if let Some(ciphers) = self.ciphers_tls_1_3.as_deref() {
             dummy_acceptor.set_ciphersuites(ciphers)?;
@@ -285,42 +400,106 @@ impl NodeConfig {
     }
 }
 
-pub struct AcmeDomainIter<'a> {
-    config: &'a NodeConfig,
-    index: usize,
-}
-
-impl<'a> AcmeDomainIter<'a> {
-    fn new(config: &'a NodeConfig) -> Self {
-        Self { config, index: 0 }
+#[test]
+fn test_acme_domain_list() -> Result<(), Error> {
+    const OLD_CONFIG: &str = "\
+        acmedomain0: a.invalid.local\n\
+        acmedomain2: c.invalid.local,alias=c.alias.invalid.local\n\
+        acmedomain1: b.invalid.local,plugin=power\n\
+    ";
+
+    let mut config: NodeConfig =
+        crate::tools::config::from_str(OLD_CONFIG, &NodeConfig::API_SCHEMA)?;
+
+    let domains: Vec<&str> = config.acme_domains().map(|d| d.domain.as_str()).collect();
+    assert_eq!(
+        domains,
+        ["a.invalid.local", "b.invalid.local", "c.invalid.local"]
+    );
+    assert_eq!(config.acme_domains[1].plugin.as_deref(), Some("power"));
+
+    let raw = crate::tools::config::to_bytes(&config, &NodeConfig::API_SCHEMA)?;
+    assert_eq!(
+        String::from_utf8(raw)?,
+        "\
+        acmedomain0: a.invalid.local\n\
+        acmedomain1: b.invalid.local,plugin=power\n\
+        acmedomain2: c.invalid.local,alias=c.alias.invalid.local\n\
+        ",
+    );
+
+    // no longer limited to five entries
+    for i in 0..5 {
+        config.set_acme_domain(AcmeDomain {
+            domain: format!("new{i}.invalid.local"),
+            alias: None,
+            plugin: None,
+        });
     }
+    config.remove_acme_domain("B.invalid.local")?;
+    assert!(config.remove_acme_domain("b.invalid.local").is_err());
+
+    let raw = crate::tools::config::to_bytes(&config, &NodeConfig::API_SCHEMA)?;
+    let config: NodeConfig =
+        crate::tools::config::from_str(std::str::from_utf8(&raw)?, &NodeConfig::API_SCHEMA)?;
+    assert_eq!(config.acme_domains.len(), 7);
+    assert_eq!(config.acme_domains[6].domain, "new4.invalid.local");
+
+    assert!(crate::tools::config::from_str::<NodeConfig>(
+        "acmedomainX: a.invalid.local\n",
+        &NodeConfig::API_SCHEMA
+    )
+    .is_err());
+
+    Ok(())
 }
 
-impl<'a> Iterator for AcmeDomainIter<'a> {
-    type Item = Result<AcmeDomain, Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let domain = loop {
-            let index = self.index;
-            self.index += 1;
-
-            let domain = match index {
-                0 => self.config.acmedomain0.as_deref(),
-                1 => self.config.acmedomain1.as_deref(),
-                2 => self.config.acmedomain2.as_deref(),
-                3 => self.config.acmedomain3.as_deref(),
-                4 => self.config.acmedomain4.as_deref(),
-                _ => return None,
-            };
-
-            if let Some(domain) = domain {
-                break domain;
-            }
-        };
-
-        Some(crate::tools::config::from_property_string(
-            domain,
-            &AcmeDomain::API_SCHEMA,
-        ))
+#[test]
+fn test_save_config_increases_generation() -> Result<(), Error> {
+    let dir = std::path::Path::new(".testdir-node-config");
+    let _ = std::fs::remove_dir_all(dir);
+
+    let cache = ConfigVersionCache::open_path(
+        &dir.join("config-versions"),
+        nix::unistd::getuid(),
+        nix::unistd::getgid(),
+    )?;
+    let conf_file = dir.join("node.cfg");
+
+    let mut config: NodeConfig = crate::tools::config::from_str("", &NodeConfig::API_SCHEMA)?;
+    let start = cache.node_config_generation();
+
+    for i in 1..=2 {
+        config.email_from = Some(format!("root{i}@invalid.local"));
+        save_config_impl(
+            &config,
+            |raw| Ok(std::fs::write(&conf_file, raw)?),
+            || Ok(Arc::clone(&cache)),
+        )?;
+        assert_eq!(cache.node_config_generation(), start + i);
     }
+    assert!(std::fs::read_to_string(&conf_file)?.contains("root2@invalid.local"));
+
+    // a config that fails validation is neither written nor announced
+    config.set_acme_domain(AcmeDomain {
+        domain: "a.invalid.local".to_string(),
+        alias: None,
+        plugin: None,
+    });
+    config.acme_domains.push(AcmeDomain {
+        domain: "A.invalid.local".to_string(),
+        alias: None,
+        plugin: None,
+    });
+    assert!(save_config_impl(
+        &config,
+        |_| panic!("invalid config must not be written"),
+        || Ok(Arc::clone(&cache)),
+    )
+    .is_err());
+    assert_eq!(cache.node_config_generation(), start + 2);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
 }
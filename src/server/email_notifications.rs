@@ -343,14 +343,8 @@ pub fn send_gc_status(
 
     let text = match result {
         Ok(()) => {
-            let deduplication_factor = if status.disk_bytes > 0 {
-                (status.index_data_bytes as f64) / (status.disk_bytes as f64)
-            } else {
-                1.0
-            };
-
             data["status"] = json!(status);
-            data["deduplication-factor"] = format!("{:.2}", deduplication_factor).into();
+            data["deduplication-factor"] = format!("{:.2}", status.deduplication_factor()).into();
 
             HANDLEBARS.render("gc_ok_template", &data)?
         }
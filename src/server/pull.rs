@@ -881,9 +881,11 @@ async fn pull_snapshot_from<'a>(
     snapshot: &'a pbs_datastore::BackupDir,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
 ) -> Result<(), Error> {
-    let (_path, is_new, _snap_lock) = snapshot
-        .datastore()
-        .create_locked_backup_dir(snapshot.backup_ns(), snapshot.as_ref())?;
+    let (_path, is_new, _snap_lock) = snapshot.datastore().create_locked_backup_dir(
+        snapshot.backup_ns(),
+        snapshot.as_ref(),
+        None,
+    )?;
 
     if is_new {
         task_log!(worker, "sync snapshot {}", snapshot.dir());
@@ -56,6 +56,7 @@ pub fn do_verification_job(
                 Some(&move |manifest| {
                     verify_filter(ignore_verified_snapshots, outdated_after, manifest)
                 }),
+                verification_job.threads.unwrap_or(1),
             );
             let job_result = match result {
                 Ok(ref failed_dirs) if failed_dirs.is_empty() => Ok(()),
@@ -55,6 +55,8 @@ pub struct TrafficControlCache {
     last_update: i64,
     last_traffic_control_generation: usize,
     rules: Vec<ParsedTcRule>,
+    // per connection upload limit for peers not matching any rule (node config 'default-bwlimit')
+    default_rate_in: Option<u64>,
     limiter_map: HashMap<String, (Option<SharedRateLimit>, Option<SharedRateLimit>)>,
     use_utc: bool, // currently only used for testing
 }
@@ -126,6 +128,7 @@ impl TrafficControlCache {
             rules: Vec::new(),
             limiter_map: HashMap::new(),
             last_traffic_control_generation: 0,
+            default_rate_in: None,
             last_update: 0,
             use_utc: false,
             last_rate_compute: Instant::now(),
@@ -137,7 +140,8 @@ impl TrafficControlCache {
     ///
     /// Only reload if configuration file was updated
     /// ([ConfigVersionCache]) or last update is older that 60
-    /// seconds.
+    /// seconds. Changes to the node config 'default-bwlimit' are
+    /// picked up by the latter.
     pub fn reload(&mut self, now: i64) {
         let version_cache = match ConfigVersionCache::new() {
             Ok(cache) => cache,
@@ -173,11 +177,29 @@ impl TrafficControlCache {
     }
 
     fn reload_impl(&mut self) -> Result<(), Error> {
+        // the node config only provides the default limit, so don't let it block rule updates
+        let default_rate_in = match crate::config::node::config() {
+            Ok((node_config, _)) => node_config.default_bwlimit.map(|limit| limit.as_u64()),
+            Err(err) => {
+                log::error!(
+                    "TrafficControlCache::reload failed to read node config -> {}",
+                    err
+                );
+                None
+            }
+        };
+        self.update_default_rate(default_rate_in);
+
         let (config, _) = pbs_config::traffic_control::config()?;
 
         self.update_config(&config)
     }
 
+    /// Set the upload rate limit applied to each connection not matching any rule.
+    fn update_default_rate(&mut self, rate_in: Option<u64>) {
+        self.default_rate_in = rate_in;
+    }
+
     /// Compute current data rates.
     ///
     /// This should be called every second (from `proxmox-backup-proxy`).
@@ -327,6 +349,8 @@ impl TrafficControlCache {
     ///
     /// - Rules where timeframe does not match are skipped.
     /// - Rules with smaller network size have higher priority.
+    /// - Without a matching rule, incoming traffic of the connection is limited
+    ///   to the node's `default-bwlimit`, if set.
     ///
     /// Behavior is undefined if more than one rule matches after
     /// above selection.
@@ -377,7 +401,13 @@ impl TrafficControlCache {
                     None => ("", None, None), // should never happen
                 }
             }
-            None => ("", None, None),
+            None => {
+                // like the client side '--rate' option, this limits each connection on its own
+                let read_limiter = self.default_rate_in.map(|rate_in| {
+                    Arc::new(Mutex::new(RateLimiter::new(rate_in, rate_in))) as SharedRateLimit
+                });
+                ("", read_limiter, None)
+            }
         }
     }
 }
@@ -489,4 +519,45 @@ rule: somewhere
 
         Ok(())
     }
+
+    #[test]
+    fn test_default_rate_limit() -> Result<(), Error> {
+        let config_data = "
+rule: rule1
+	network 192.168.2.0/24
+	rate-in 50000000
+";
+        let config = pbs_config::traffic_control::CONFIG.parse("testconfig", config_data)?;
+
+        let mut cache = TrafficControlCache::new();
+        cache.use_utc = true;
+        cache.use_shared_memory = false; // avoid permission problems in test environment
+
+        cache.update_config(&config)?;
+
+        let gateway = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 2, 1)), 1234);
+        let somewhere = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 1234);
+
+        let (rule, read_limiter, write_limiter) = cache.lookup_rate_limiter(somewhere, 0);
+        assert_eq!(rule, "");
+        assert!(read_limiter.is_none());
+        assert!(write_limiter.is_none());
+
+        cache.update_default_rate(Some(10_000_000));
+
+        // only uploads of otherwise unlimited peers are limited
+        let (rule, read_limiter, write_limiter) = cache.lookup_rate_limiter(somewhere, 0);
+        assert_eq!(rule, "");
+        assert!(read_limiter.is_some());
+        assert!(write_limiter.is_none());
+
+        let (rule, _read_limiter, _write_limiter) = cache.lookup_rate_limiter(gateway, 0);
+        assert_eq!(rule, "rule1");
+
+        cache.update_default_rate(None);
+        let (_rule, read_limiter, _write_limiter) = cache.lookup_rate_limiter(somewhere, 0);
+        assert!(read_limiter.is_none());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+
+use proxmox_sys::WorkerTaskContext;
+
+use pbs_api_types::{BackupNamespace, BackupType, CryptMode, DatastoreFSyncLevel, UPID};
+use pbs_datastore::data_blob::DataChunkBuilder;
+use pbs_datastore::index::IndexFile;
+use pbs_datastore::manifest::{BackupManifest, MANIFEST_BLOB_NAME};
+use pbs_datastore::{ChunkStore, DataBlob, DataStore, StoreProgress};
+
+use proxmox_backup::backup::{verify_backup_group, VerifyWorker};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Default)]
+struct LogWorker(Mutex<Vec<String>>);
+
+impl WorkerTaskContext for LogWorker {
+    fn abort_requested(&self) -> bool {
+        false
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        false
+    }
+
+    fn log(&self, level: log::Level, message: &std::fmt::Arguments) {
+        println!("{level}: {message}");
+        self.0.lock().unwrap().push(message.to_string());
+    }
+}
+
+#[test]
+fn verify_group_reads_shared_chunk_once() -> Result<(), Error> {
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-verify-group");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+    ChunkStore::create(
+        "verify-group",
+        &path,
+        user.uid,
+        user.gid,
+        None,
+        DatastoreFSyncLevel::None,
+    )?;
+    let store = unsafe { DataStore::open_path("verify-group", &path, None)? };
+
+    let group = store.backup_group_from_parts(BackupNamespace::root(), BackupType::Vm, "100");
+    std::fs::create_dir_all(group.full_group_path())?;
+
+    // uncompressed, so that every chunk read shows up as 1 MiB in the log
+    let (shared_chunk, shared_digest) = DataChunkBuilder::new(&vec![1u8; CHUNK_SIZE])
+        .compress(false)
+        .build()?;
+    store.insert_chunk(&shared_chunk, &shared_digest)?;
+
+    for (backup_time, fill) in [(1_600_000_000, 2u8), (1_600_000_100, 3u8)] {
+        let backup_dir = group.backup_dir(backup_time)?;
+        std::fs::create_dir(backup_dir.full_path())?;
+
+        let (chunk, digest) = DataChunkBuilder::new(&vec![fill; CHUNK_SIZE])
+            .compress(false)
+            .build()?;
+        store.insert_chunk(&chunk, &digest)?;
+
+        let mut index_path = backup_dir.relative_path();
+        index_path.push("disk.img.fidx");
+        let mut writer = store.create_fixed_writer(&index_path, 2 * CHUNK_SIZE, CHUNK_SIZE)?;
+        writer.add_digest(0, &shared_digest)?;
+        writer.add_digest(1, &digest)?;
+        writer.close()?;
+
+        let (csum, size) = store.open_fixed_reader(&index_path)?.compute_csum();
+        let mut manifest = BackupManifest::new(backup_dir.dir().clone());
+        manifest.add_file("disk.img.fidx".into(), size, csum, CryptMode::None)?;
+
+        let manifest = serde_json::to_string_pretty(&serde_json::to_value(manifest)?)?;
+        let blob = DataBlob::encode(manifest.as_bytes(), None, true)?;
+        std::fs::write(
+            backup_dir.full_path().join(MANIFEST_BLOB_NAME),
+            blob.raw_data(),
+        )?;
+    }
+
+    let worker = Arc::new(LogWorker::default());
+    let verify_worker = VerifyWorker::new(worker.clone(), store.clone());
+
+    let upid: UPID =
+        "UPID:pbs:000001D7:00000B6E:00000000:6537A1F2:verificationjob:verify-group:root@pam:"
+            .parse()?;
+
+    let mut progress = StoreProgress::new(1);
+    let failed = verify_backup_group(&verify_worker, &group, &mut progress, &upid, None, 2)?;
+    assert!(failed.is_empty());
+    assert_eq!(progress.done_snapshots, 2);
+
+    // "  verified <read>/<decoded> MiB in ..." is logged for every index
+    let read_mib: f64 = worker
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|line| line.trim_start().strip_prefix("verified "))
+        .map(|line| line.split('/').next().unwrap().parse::<f64>().unwrap())
+        .sum();
+    assert!((read_mib - 3.0).abs() < 0.1, "read {read_mib} MiB");
+
+    for info in group.list_backups()? {
+        let (manifest, _) = info.backup_dir.load_manifest()?;
+        assert_eq!(manifest.unprotected["verify_state"]["state"], "ok");
+    }
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
@@ -164,5 +164,18 @@ Ext.define('PBS.window.VerifyJobEdit', {
 		},
 	    },
 	],
+	advancedColumn2: [
+	    {
+		xtype: 'proxmoxintegerfield',
+		fieldLabel: gettext('Threads'),
+		name: 'threads',
+		minValue: 1,
+		maxValue: 32,
+		emptyText: '1',
+		cbind: {
+		    deleteEmpty: '{!isCreate}',
+		},
+	    },
+	],
     },
 });